@@ -1,4 +1,40 @@
+/// Fields of auth.proto and admin.proto with the bytes type, which are base64 encoded in JSON like the proto3 JSON mapping does.
+const BYTES_FIELDS: &[&str] = &[
+    "RegisterRequest.y1",
+    "RegisterRequest.y2",
+    "AuthenticationChallengeRequest.r1",
+    "AuthenticationChallengeRequest.r2",
+    "AuthenticationChallengeResponse.c",
+    "AuthenticationAnswerRequest.s",
+    "RotateKeysRequest.s",
+    "RotateKeysRequest.y1",
+    "RotateKeysRequest.y2",
+    "User.y1",
+    "User.y2",
+];
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/zkp_auth/auth.proto")?;
+    // The generated code is only used by the grpc features, which are what pull in tonic and prost. The client
+    // stubs and the service traits are only generated for the side of the connection which is enabled.
+    if std::env::var_os("CARGO_FEATURE_GRPC").is_some() {
+        // The server also speaks JSON, for which the messages are (de)serialized with serde, and describes the
+        // service to reflection clients with the descriptor set.
+        let out_dir = std::path::PathBuf::from(std::env::var("OUT_DIR")?);
+        let mut builder = tonic_build::configure()
+            .file_descriptor_set_path(out_dir.join("zkp_auth_descriptor.bin"))
+            .build_client(std::env::var_os("CARGO_FEATURE_GRPC_CLIENT").is_some())
+            .build_server(std::env::var_os("CARGO_FEATURE_GRPC_SERVER").is_some())
+            .message_attribute(
+                ".zkp_auth",
+                r#"#[cfg_attr(feature = "grpc-server", derive(serde::Serialize, serde::Deserialize), serde(default, rename_all = "camelCase"))]"#,
+            );
+        for field in BYTES_FIELDS {
+            builder = builder.field_attribute(
+                format!(".zkp_auth.{}", field),
+                r#"#[cfg_attr(feature = "grpc-server", serde(with = "crate::proto::base64_bytes"))]"#,
+            );
+        }
+        builder.compile_protos(&["proto/zkp_auth/auth.proto", "proto/zkp_auth/admin.proto"], &["proto/zkp_auth"])?;
+    }
     Ok(())
 }
@@ -0,0 +1,660 @@
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The hash the first record of a log is chained to.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// The shortest key accepted by AuditKey::load, in bytes.
+pub const MIN_KEY_LEN: usize = 32;
+
+/// How many records can wait for the writer of an AuditLog before appending blocks.
+pub const QUEUE_LEN: usize = 1024;
+
+/// AuditKey keys the hashes of the records and of the head with HMAC-SHA256. Without the key, whoever can write the
+/// log could recompute the chain and the head after truncating or rewriting it.
+#[derive(Clone)]
+pub struct AuditKey(Vec<u8>);
+
+impl AuditKey {
+    /// new creates a key from the given bytes.
+    pub fn new(key: impl Into<Vec<u8>>) -> Self {
+        AuditKey(key.into())
+    }
+
+    /// load reads the key from the first line of the file. Keys shorter than MIN_KEY_LEN bytes are rejected.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, AuditError> {
+        let contents = fs::read_to_string(path)?;
+        let key = contents.lines().next().unwrap_or_default().trim();
+        if key.len() < MIN_KEY_LEN {
+            return Err(AuditError::ShortKey);
+        }
+        Ok(AuditKey::new(key))
+    }
+
+    /// mac returns the hex encoded HMAC-SHA256 of the message.
+    fn mac(&self, message: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC takes keys of any length");
+        mac.update(message);
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+impl fmt::Debug for AuditKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("AuditKey(..)")
+    }
+}
+
+/// Event is what an audit record is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Event {
+    // Register is a registration of a user
+    Register,
+    // Challenge is a challenge requested for a user
+    Challenge,
+    // Verify is an answer to a challenge, whether for a login or a key rotation
+    Verify,
+    // RotateKeys is a replacement of the public keys of a user
+    RotateKeys,
+    // Logout is the end of a session
+    Logout,
+    // DisableUser is an administrator keeping a user from authenticating
+    DisableUser,
+    // EnableUser is an administrator letting a disabled user authenticate again
+    EnableUser,
+    // DeleteUser is an administrator removing a user
+    DeleteUser,
+    // RevokeSessions is an administrator ending all sessions of a user
+    RevokeSessions,
+}
+
+/// AuditRecord is one line of the audit log. Its hash covers all other fields including the hash of the previous
+/// record, so that no record can be changed, removed or inserted without breaking the chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditRecord {
+    // seq is the position of the record in the log, starting at 0
+    pub seq: u64,
+    // time_ms is when the event happened in milliseconds since the Unix epoch
+    pub time_ms: u64,
+    pub event: Event,
+    // realm is the realm the event happened in; it is left out for the default realm, so that records written before
+    // there were realms keep their hashes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub realm: Option<String>,
+    // user is the user the event is about if it is known
+    pub user: Option<String>,
+    // auth_id is the challenge the event is about if there is one
+    pub auth_id: Option<String>,
+    // outcome is "ok" or the gRPC status code of the failure; verifications have the outcomes of the metrics
+    pub outcome: String,
+    // prev is the hash of the previous record, or GENESIS_HASH for the first one
+    pub prev: String,
+    // hash is the hex encoded SHA-256, or HMAC-SHA256 if the log is keyed, of the record serialized with an empty hash
+    pub hash: String,
+}
+
+impl AuditRecord {
+    /// compute_hash returns the hash the record should have in a log keyed with the given key, if any.
+    pub fn compute_hash(&self, key: Option<&AuditKey>) -> String {
+        let unhashed = AuditRecord { hash: String::new(), ..self.clone() };
+        let json = serde_json::to_vec(&unhashed).expect("records serialize to JSON");
+        match key {
+            Some(key) => key.mac(&json),
+            None => hex::encode(Sha256::digest(json)),
+        }
+    }
+}
+
+/// Head is the sequence number and hash of the last record, kept next to the log so that truncation can be
+/// detected. In a keyed log it carries a MAC, so that it cannot be moved to an earlier record without the key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Head {
+    pub seq: u64,
+    pub hash: String,
+    // mac is the hex encoded HMAC-SHA256 of the sequence number and hash; it is left out in logs without a key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mac: Option<String>,
+}
+
+impl Head {
+    /// new creates the head naming the record with the given sequence number and hash.
+    pub fn new(seq: u64, hash: String, key: Option<&AuditKey>) -> Self {
+        // The MAC covers a different message than record hashes, so that no record hash can pass as a head MAC.
+        let mac = key.map(|key| key.mac(format!("head:{}:{}", seq, hash).as_bytes()));
+        Head { seq, hash, mac }
+    }
+}
+
+/// AuditError is returned when an audit log cannot be read or written or does not verify.
+#[derive(Debug)]
+pub enum AuditError {
+    // Io means the log or its head could not be read or written
+    Io(io::Error),
+    // Malformed means the line with the given number, starting at 1, is not a record
+    Malformed { line: u64 },
+    // Tampered means the record at the given position does not match its hash or does not follow its predecessor
+    Tampered { seq: u64 },
+    // Truncated means the log ends before the record its head names
+    Truncated { records: u64, expected: u64 },
+    // HeadMismatch means the head does not name the last record of the log or, in a keyed log, its MAC is wrong
+    HeadMismatch,
+    // ShortKey means the audit key file holds fewer than MIN_KEY_LEN bytes
+    ShortKey,
+    // Stopped means appending a record failed with the given error, after which the log takes no further records
+    Stopped(String),
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Io(err) => write!(f, "could not access the audit log: {}", err),
+            AuditError::Malformed { line } => write!(f, "line {} is not an audit record", line),
+            AuditError::Tampered { seq } => write!(f, "record {} has been tampered with", seq),
+            AuditError::Truncated { records, expected } => {
+                write!(f, "the log has been truncated: it has {} records but its head expects {}", records, expected)
+            }
+            AuditError::HeadMismatch => write!(f, "the last record does not match the head of the log"),
+            AuditError::ShortKey => write!(f, "the audit key must be at least {} bytes long", MIN_KEY_LEN),
+            AuditError::Stopped(err) => write!(f, "the audit log stopped after failing to append a record: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AuditError {}
+
+impl From<io::Error> for AuditError {
+    fn from(err: io::Error) -> Self {
+        AuditError::Io(err)
+    }
+}
+
+/// head_path returns where the head of the log at path is kept: next to it with `.head` appended.
+pub fn head_path(path: &Path) -> PathBuf {
+    let mut head = path.as_os_str().to_owned();
+    head.push(".head");
+    PathBuf::from(head)
+}
+
+/// Chain is what reading a log found.
+#[derive(Debug, Default)]
+struct Chain {
+    // last and previous are the heads of the last two records
+    last: Option<Head>,
+    previous: Option<Head>,
+    // records is the number of complete records
+    records: u64,
+    // complete_len is the length of the log up to the end of its last complete line
+    complete_len: u64,
+    // torn is true if the log ends with an incomplete line, as left by a crash in the middle of writing a record
+    torn: bool,
+}
+
+/// read_head reads the head of the log at path, if there is one.
+fn read_head(path: &Path) -> Result<Option<Head>, AuditError> {
+    match fs::read(head_path(path)) {
+        Ok(contents) => Ok(Some(serde_json::from_slice::<Head>(&contents).map_err(|_| AuditError::HeadMismatch)?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// write_head replaces the head of the log. The head is written to a temporary file which is synced and then renamed
+/// over the old head, so that it is never half written.
+fn write_head(head_path: &Path, head: &Head) -> Result<(), AuditError> {
+    let tmp = head_path.with_extension("head.tmp");
+    let mut file = fs::File::create(&tmp)?;
+    file.write_all(&serde_json::to_vec(head).expect("heads serialize to JSON"))?;
+    file.sync_all()?;
+    fs::rename(&tmp, head_path)?;
+    Ok(())
+}
+
+/// read_chain checks the hash chain of the complete lines of the log at path, keyed with the given key if any. A
+/// missing log has no records.
+fn read_chain(path: &Path, key: Option<&AuditKey>) -> Result<Chain, AuditError> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Chain::default()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut chain = Chain::default();
+    let mut reader = BufReader::new(file);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        let len = reader.read_until(b'\n', &mut line)?;
+        if len == 0 {
+            break;
+        }
+        if line.last() != Some(&b'\n') {
+            chain.torn = true;
+            break;
+        }
+        let record: AuditRecord =
+            serde_json::from_slice(&line[..len - 1]).map_err(|_| AuditError::Malformed { line: chain.records + 1 })?;
+        let (expected_seq, expected_prev) = match &chain.last {
+            Some(last) => (last.seq + 1, last.hash.as_str()),
+            None => (0, GENESIS_HASH),
+        };
+        if record.seq != expected_seq || record.prev != expected_prev || record.hash != record.compute_hash(key) {
+            return Err(AuditError::Tampered { seq: expected_seq });
+        }
+        chain.previous = chain.last.replace(Head::new(record.seq, record.hash, key));
+        chain.records += 1;
+        chain.complete_len += len as u64;
+    }
+    Ok(chain)
+}
+
+/// check compares the end of the chain with the head, returning the head of the last record.
+fn check(head: &Option<Head>, chain: &Chain) -> Result<Option<Head>, AuditError> {
+    match (head, &chain.last) {
+        (None, None) => Ok(None),
+        (Some(head), Some(last)) if head == last => Ok(Some(head.clone())),
+        (Some(head), _) if head.seq >= chain.records => Err(AuditError::Truncated { records: chain.records, expected: head.seq + 1 }),
+        _ => Err(AuditError::HeadMismatch),
+    }
+}
+
+/// verify checks the hash chain of the log at path and compares its end with the head, returning the head of the
+/// last record. An empty or missing log verifies if it has no head either. A keyed log only verifies with its key.
+pub fn verify(path: impl AsRef<Path>, key: Option<&AuditKey>) -> Result<Option<Head>, AuditError> {
+    let path = path.as_ref();
+    let head = read_head(path)?;
+    let chain = read_chain(path, key)?;
+    if chain.torn {
+        return Err(AuditError::Malformed { line: chain.records + 1 });
+    }
+    check(&head, &chain)
+}
+
+/// Writer owns the file of an AuditLog and the end of its chain. It runs on a thread of its own, so that neither the
+/// blocking writes nor the syncs hold up the callers.
+#[derive(Debug)]
+struct Writer {
+    file: fs::File,
+    head_path: PathBuf,
+    key: Option<AuditKey>,
+    next_seq: u64,
+    prev: String,
+    // error is the error which stopped the writer; a failed append may have left a record on disk whose head was
+    // not written, so chaining further records to next_seq and prev would break the chain
+    error: Option<String>,
+    // failed is shared with the AuditLog, which reports it as unhealthy once the writer stopped
+    failed: Arc<AtomicBool>,
+}
+
+/// Message is what an AuditLog sends to its writer.
+enum Message {
+    // Append is a record to chain to the log; its seq, prev and hash are filled in by the writer
+    Append(AuditRecord),
+    // Sync asks for the records written so far to reach the disk and for the result
+    Sync(mpsc::Sender<Result<(), AuditError>>),
+}
+
+impl Writer {
+    /// run handles the messages until the AuditLog is dropped.
+    fn run(mut self, messages: mpsc::Receiver<Message>) {
+        for message in messages {
+            match message {
+                Message::Append(record) => {
+                    if let Some(err) = &self.error {
+                        tracing::error!("Dropping a {:?} record, the audit log stopped after an error: {}", record.event, err);
+                    } else if let Err(err) = self.append(record) {
+                        tracing::error!("Failed to append to the audit log, it takes no further records: {}", err);
+                        self.error = Some(err.to_string());
+                        self.failed.store(true, Ordering::Relaxed);
+                    }
+                }
+                Message::Sync(reply) => {
+                    let result = match &self.error {
+                        Some(err) => Err(AuditError::Stopped(err.clone())),
+                        None => self.file.sync_all().map_err(AuditError::from),
+                    };
+                    let _ = reply.send(result);
+                }
+            }
+        }
+    }
+
+    /// append chains the record to the log.
+    fn append(&mut self, mut record: AuditRecord) -> Result<(), AuditError> {
+        record.seq = self.next_seq;
+        record.prev = self.prev.clone();
+        record.hash = record.compute_hash(self.key.as_ref());
+
+        let mut line = serde_json::to_vec(&record).expect("records serialize to JSON");
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        // The record is synced before the head names it, so that the head never runs ahead of the log after a
+        // power loss.
+        self.file.sync_data()?;
+        write_head(&self.head_path, &Head::new(record.seq, record.hash.clone(), self.key.as_ref()))?;
+
+        self.next_seq += 1;
+        self.prev = record.hash;
+        Ok(())
+    }
+}
+
+/// AuditLog appends hash-chained records to a file, one JSON object per line. The records are written by a thread
+/// of its own in the order they were appended. The first record which cannot be written stops the log, so that the
+/// chain on disk stays verifiable, and makes it unhealthy.
+#[derive(Debug)]
+pub struct AuditLog {
+    // sender hands the records to the writer; it is None once the log is dropped
+    sender: Option<mpsc::SyncSender<Message>>,
+    // failed is set by the writer when it stops after an error
+    failed: Arc<AtomicBool>,
+    // writer is the thread writing the records
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl AuditLog {
+    /// open verifies the log at path and opens it for appending, creating it if it does not exist. It fails if the
+    /// existing log does not verify, so that a tampered log is not extended. What a crash while appending can leave
+    /// behind is repaired: an incomplete last line is cut off, and a head one record behind the log is moved forward.
+    /// A log with records but no head is not repaired, since it cannot be told apart from a log cut off after its
+    /// first record. The records and the head are keyed with the key if one is given.
+    pub fn open(path: impl AsRef<Path>, key: Option<AuditKey>) -> Result<Self, AuditError> {
+        let path = path.as_ref();
+        let head = read_head(path)?;
+        let chain = read_chain(path, key.as_ref())?;
+        if chain.torn {
+            // The record was not synced, so its head was never written and nobody has been told about it.
+            tracing::warn!("Cutting off the incomplete last line of the audit log {}", path.display());
+            fs::OpenOptions::new().write(true).open(path)?.set_len(chain.complete_len)?;
+        }
+        let head = match (&head, &chain.previous) {
+            // The last record reached the disk but its head did not, either because the crash came in between or
+            // because the rename of the head was not synced yet. In a keyed log only the key can have made it.
+            (Some(head), Some(previous)) if head == previous => {
+                tracing::warn!("Moving the head of the audit log {} to its last record", path.display());
+                let last = chain.last.clone().expect("the log has records");
+                write_head(&head_path(path), &last)?;
+                Some(last)
+            }
+            _ => check(&head, &chain)?,
+        };
+        let file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+        let (next_seq, prev) = match head {
+            Some(head) => (head.seq + 1, head.hash),
+            None => (0, GENESIS_HASH.to_string()),
+        };
+        let failed = Arc::new(AtomicBool::new(false));
+        let writer = Writer { file, head_path: head_path(path), key, next_seq, prev, error: None, failed: failed.clone() };
+        let (sender, messages) = mpsc::sync_channel(QUEUE_LEN);
+        let writer = thread::Builder::new().name("audit-log".to_string()).spawn(move || writer.run(messages))?;
+        Ok(AuditLog { sender: Some(sender), failed, writer: Some(writer) })
+    }
+
+    /// append queues a record of the event for the log without waiting for it to be written. The realm is None for
+    /// the default realm. If QUEUE_LEN records are waiting already, append blocks until the writer catches up, so
+    /// that a disk which cannot keep up slows the callers down instead of records piling up in memory or being lost.
+    /// Failures to write are logged, stop the log and are reported by is_healthy and sync.
+    pub fn append(&self, event: Event, realm: Option<&str>, user: Option<&str>, auth_id: Option<&str>, outcome: &str) {
+        let time_ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_millis() as u64).unwrap_or(0);
+        let record = AuditRecord {
+            seq: 0,
+            time_ms,
+            event,
+            realm: realm.map(str::to_string),
+            user: user.map(str::to_string),
+            auth_id: auth_id.map(str::to_string),
+            outcome: outcome.to_string(),
+            prev: String::new(),
+            hash: String::new(),
+        };
+        if self.send(Message::Append(record)).is_err() {
+            tracing::error!("Failed to append to the audit log: its writer is gone");
+        }
+    }
+
+    /// is_healthy returns false once the log stopped after failing to append a record.
+    pub fn is_healthy(&self) -> bool {
+        !self.failed.load(Ordering::Relaxed)
+    }
+
+    /// sync waits until the records appended so far have reached the disk. It fails if the log stopped after failing
+    /// to append a record.
+    pub fn sync(&self) -> Result<(), AuditError> {
+        let (reply, result) = mpsc::channel();
+        self.send(Message::Sync(reply))?;
+        result.recv().map_err(|_| writer_gone())?
+    }
+
+    /// send hands the message to the writer.
+    fn send(&self, message: Message) -> Result<(), AuditError> {
+        self.sender.as_ref().expect("the sender is only taken on drop").send(message).map_err(|_| writer_gone())
+    }
+}
+
+impl Drop for AuditLog {
+    /// drop waits for the records appended so far to be written.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// writer_gone is the error of an AuditLog whose writer panicked.
+fn writer_gone() -> AuditError {
+    io::Error::other("the audit log writer is gone").into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// temp_log returns the path of a log which does not exist yet.
+    fn temp_log() -> PathBuf {
+        std::env::temp_dir().join(format!("zkp-auth-audit-{}.log", rand::random::<u64>()))
+    }
+
+    /// records returns the records of the log at path.
+    fn records(path: &Path) -> Vec<AuditRecord> {
+        fs::read_to_string(path).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    fn remove(path: &Path) {
+        fs::remove_file(path).unwrap();
+        fs::remove_file(head_path(path)).unwrap();
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        let path = temp_log();
+        assert_eq!(verify(&path, None).unwrap(), None);
+
+        let log = AuditLog::open(&path, None).unwrap();
+        log.append(Event::Register, None, Some("alice"), None, "ok");
+        log.sync().unwrap();
+        let first = records(&path).remove(0);
+        assert_eq!((first.seq, first.prev.as_str()), (0, GENESIS_HASH));
+        log.append(Event::Challenge, None, Some("alice"), Some("id-1"), "ok");
+        drop(log);
+
+        // a reopened log continues the chain
+        let log = AuditLog::open(&path, None).unwrap();
+        log.append(Event::Verify, Some("shop"), Some("alice"), Some("id-1"), "success");
+        log.sync().unwrap();
+        let last = records(&path).pop().unwrap();
+        assert_eq!(last.seq, 2);
+        assert_eq!(verify(&path, None).unwrap(), Some(Head::new(2, last.hash, None)));
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().count(), 3);
+        // only records of other realms than the default one name their realm
+        assert_eq!(contents.matches("\"realm\":\"shop\"").count(), 1);
+        assert_eq!(contents.matches("realm").count(), 1);
+        remove(&path);
+    }
+
+    #[test]
+    fn test_recovery() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, None).unwrap();
+        log.append(Event::Register, None, Some("alice"), None, "ok");
+        log.append(Event::Register, None, Some("bob"), None, "ok");
+        log.sync().unwrap();
+        let behind = fs::read(head_path(&path)).unwrap();
+        log.append(Event::Register, None, Some("carol"), None, "ok");
+        drop(log);
+        let last = records(&path).pop().unwrap();
+
+        // a crash before the head of the last record was written
+        fs::write(head_path(&path), &behind).unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::HeadMismatch)));
+        AuditLog::open(&path, None).unwrap();
+        assert_eq!(verify(&path, None).unwrap(), Some(Head::new(2, last.hash.clone(), None)));
+
+        // a crash while a record was written
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"seq\":3,\"time").unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::Malformed { line: 4 })));
+        let log = AuditLog::open(&path, None).unwrap();
+        assert_eq!(verify(&path, None).unwrap(), Some(Head::new(2, last.hash, None)));
+        log.append(Event::Logout, None, Some("alice"), None, "ok");
+        drop(log);
+        assert_eq!(records(&path).pop().unwrap().seq, 3);
+        verify(&path, None).unwrap();
+
+        // only one record can be missing from the head
+        fs::write(head_path(&path), &behind).unwrap();
+        assert!(matches!(AuditLog::open(&path, None), Err(AuditError::HeadMismatch)));
+        remove(&path);
+    }
+
+    #[test]
+    fn test_failed_append() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, None).unwrap();
+        log.append(Event::Register, None, Some("alice"), None, "ok");
+        log.sync().unwrap();
+        assert!(log.is_healthy());
+
+        // the record reaches the log but its head cannot replace the old one
+        fs::remove_file(head_path(&path)).unwrap();
+        fs::create_dir(head_path(&path)).unwrap();
+        log.append(Event::Register, None, Some("bob"), None, "ok");
+        log.append(Event::Register, None, Some("carol"), None, "ok");
+        assert!(matches!(log.sync(), Err(AuditError::Stopped(_))));
+        assert!(matches!(log.sync(), Err(AuditError::Stopped(_))));
+        assert!(!log.is_healthy());
+        drop(log);
+
+        // no record is chained to the one whose head is missing
+        let records = records(&path);
+        assert_eq!(records.iter().map(|record| record.user.as_deref()).collect::<Vec<_>>(), [Some("alice"), Some("bob")]);
+        fs::remove_dir(head_path(&path)).unwrap();
+        fs::remove_file(head_path(&path).with_extension("head.tmp")).unwrap();
+        fs::write(head_path(&path), serde_json::to_vec(&Head::new(1, records[1].hash.clone(), None)).unwrap()).unwrap();
+        verify(&path, None).unwrap();
+        remove(&path);
+    }
+
+    #[test]
+    fn test_keyed() {
+        let path = temp_log();
+        let key = AuditKey::new("0123456789abcdef0123456789abcdef");
+        let log = AuditLog::open(&path, Some(key.clone())).unwrap();
+        for user in ["alice", "bob", "carol"] {
+            log.append(Event::Register, None, Some(user), None, "ok");
+        }
+        drop(log);
+        let original = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+        assert_eq!(verify(&path, Some(&key)).unwrap().unwrap().seq, 2);
+        // the log only verifies with its key
+        assert!(matches!(verify(&path, None), Err(AuditError::Tampered { seq: 0 })));
+        assert!(matches!(verify(&path, Some(&AuditKey::new("another key"))), Err(AuditError::Tampered { seq: 0 })));
+
+        // cutting off the end and moving the head to the new last record needs the key
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        let second: AuditRecord = serde_json::from_str(lines[1]).unwrap();
+        let forged = Head::new(1, second.hash.clone(), None);
+        fs::write(head_path(&path), serde_json::to_vec(&forged).unwrap()).unwrap();
+        assert!(matches!(verify(&path, Some(&key)), Err(AuditError::HeadMismatch)));
+        let forged = Head::new(1, second.hash, Some(&AuditKey::new("another key")));
+        fs::write(head_path(&path), serde_json::to_vec(&forged).unwrap()).unwrap();
+        assert!(matches!(verify(&path, Some(&key)), Err(AuditError::HeadMismatch)));
+        assert!(matches!(AuditLog::open(&path, Some(key.clone())), Err(AuditError::HeadMismatch)));
+
+        // and so does cutting off all but the first record and deleting the head
+        let head = fs::read(head_path(&path)).unwrap();
+        fs::write(&path, format!("{}\n", lines[0])).unwrap();
+        fs::remove_file(head_path(&path)).unwrap();
+        assert!(matches!(verify(&path, Some(&key)), Err(AuditError::HeadMismatch)));
+        assert!(matches!(AuditLog::open(&path, Some(key.clone())), Err(AuditError::HeadMismatch)));
+        assert!(!head_path(&path).exists());
+        fs::write(head_path(&path), head).unwrap();
+
+        // so does appending a record the server would take for one whose head was lost in a crash
+        fs::write(&path, &original).unwrap();
+        let last: AuditRecord = serde_json::from_str(lines[2]).unwrap();
+        let mut appended = AuditRecord { seq: 3, prev: last.hash.clone(), user: Some("eve".to_string()), ..last };
+        appended.hash = appended.compute_hash(None);
+        fs::write(&path, format!("{}{}\n", original, serde_json::to_string(&appended).unwrap())).unwrap();
+        assert!(matches!(AuditLog::open(&path, Some(key.clone())), Err(AuditError::Tampered { seq: 3 })));
+        remove(&path);
+    }
+
+    #[test]
+    fn test_load_key() {
+        let path = temp_log();
+        fs::write(&path, "too short\n").unwrap();
+        assert!(matches!(AuditKey::load(&path), Err(AuditError::ShortKey)));
+        fs::write(&path, "0123456789abcdef0123456789abcdef\nignored\n").unwrap();
+        let key = AuditKey::load(&path).unwrap();
+        assert_eq!(key.0, b"0123456789abcdef0123456789abcdef");
+        assert_eq!(format!("{:?}", key), "AuditKey(..)");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampering() {
+        let path = temp_log();
+        let log = AuditLog::open(&path, None).unwrap();
+        for user in ["alice", "bob", "carol"] {
+            log.append(Event::Register, None, Some(user), None, "ok");
+        }
+        drop(log);
+        let original = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = original.lines().collect();
+
+        // changing a record
+        fs::write(&path, original.replace("\"bob\"", "\"eve\"")).unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::Tampered { seq: 1 })));
+        // removing one
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[2])).unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::Tampered { seq: 1 })));
+        // cutting off the end
+        fs::write(&path, format!("{}\n{}\n", lines[0], lines[1])).unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::Truncated { records: 2, expected: 3 })));
+        assert!(matches!(AuditLog::open(&path, None), Err(AuditError::Truncated { .. })));
+        // a half written line
+        fs::write(&path, format!("{}\n{}", lines[0], &lines[1][..20])).unwrap();
+        assert!(matches!(verify(&path, None), Err(AuditError::Malformed { line: 2 })));
+
+        fs::write(&path, &original).unwrap();
+        verify(&path, None).unwrap();
+        remove(&path);
+    }
+}
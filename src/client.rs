@@ -1,57 +1,500 @@
+use clap::{Args, Parser, Subcommand};
 use num_bigint::BigUint;
-use std::env;
-use zkp_auth::{auth_client::AuthClient, RegisterRequest, AuthenticationAnswerRequest, AuthenticationChallengeRequest};
-use ::zkp_auth::{gen_random_number_below, ZKP};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead},
+    path::PathBuf,
+    process::ExitCode,
+};
+use tonic::{transport::Endpoint, Code};
+use zkp_auth::{
+    gen_random_number_below,
+    keystore::{Keystore, Profile},
+    protocol::PublicKeys,
+    sdk::{AuthClientSdk, SdkError},
+    secret,
+    session::unix_seconds,
+    tls, DEFAULT_GROUP_ID, GROUP_IDS, ZKP,
+};
 
-pub mod zkp_auth {
-    tonic::include_proto!("zkp_auth");
+/// The server connected to unless one is given on the command line or in the profile.
+const DEFAULT_SERVER: &str = "http://127.0.0.1:50051";
+
+/// Cli holds the command line arguments of the client.
+#[derive(Debug, Parser)]
+#[command(name = "zkpauth-client", version, about = "Chaum-Pedersen ZKP authentication client")]
+struct Cli {
+    /// Address of the server, http://127.0.0.1:50051 unless the profile says otherwise; https:// addresses are
+    /// connected to with TLS
+    #[arg(long, global = true, env = "SERVER_ADDR")]
+    server: Option<String>,
+    /// Name of the user
+    #[arg(short, long, global = true, env = "ZKPAUTH_USER")]
+    user: Option<String>,
+    /// Realm of the server the user belongs to; the server's default realm if not given
+    #[arg(long, global = true, env = "ZKPAUTH_REALM")]
+    realm: Option<String>,
+    /// Group the secret belongs to, which has to be the one of the realm; profiles store the group of their secret
+    #[arg(long, global = true, env = "ZKPAUTH_GROUP")]
+    group: Option<String>,
+    /// Keystore profile holding the user, the server and the secret
+    #[arg(short, long, global = true, env = "ZKPAUTH_PROFILE", conflicts_with = "keyfile")]
+    profile: Option<String>,
+    /// File holding the secret; without it or a profile the secret is derived from a password
+    #[arg(short, long, global = true, env = "ZKPAUTH_KEYFILE")]
+    keyfile: Option<PathBuf>,
+    /// Read passwords and passphrases from stdin, one per line, instead of prompting for them
+    #[arg(long, global = true)]
+    password_stdin: bool,
+    /// Print the result as JSON
+    #[arg(long, global = true)]
+    json: bool,
+    #[command(flatten)]
+    tls: TlsArgs,
+    #[command(subcommand)]
+    command: Command,
 }
 
-#[tokio::main]
-async fn main() {
-    let (g, h, p, q) = ::zkp_auth::default_cfg();
-    let zkp = ZKP { g: g.clone(), h: h.clone(), p: p.clone(), q: q.clone() };
+/// TlsArgs configure the TLS connection to https:// servers.
+#[derive(Debug, Args)]
+struct TlsArgs {
+    /// CA certificate the server certificate must be signed by; defaults to the system's trusted roots
+    #[arg(long, global = true, env = "SERVER_CA_FILE")]
+    ca_file: Option<PathBuf>,
+    /// Certificate presented to servers requiring mutual TLS
+    #[arg(long, global = true, env = "CLIENT_CERT_FILE", requires = "client_key")]
+    client_cert: Option<PathBuf>,
+    /// Private key of the client certificate
+    #[arg(long, global = true, env = "CLIENT_KEY_FILE", requires = "client_cert")]
+    client_key: Option<PathBuf>,
+    /// Host name the server certificate is checked for instead of the host of the server address
+    #[arg(long, global = true, env = "SERVER_TLS_DOMAIN")]
+    tls_domain: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Generate a random secret and store it in the key file or a new keystore profile
+    Keygen,
+    /// Register the user with the public keys of its secret
+    Register,
+    /// Prove knowledge of the secret and start a session
+    Login {
+        /// Register the user first if it is not registered yet
+        #[arg(long)]
+        register: bool,
+    },
+    /// Replace the public keys of the user with the ones of a new secret
+    Rotate {
+        /// File holding the new secret; without it the new secret is generated when using a profile, and derived
+        /// from a new password otherwise
+        #[arg(long)]
+        new_keyfile: Option<PathBuf>,
+    },
+    /// End the current session
+    Logout,
+    /// Show who the current session belongs to
+    Whoami,
+    /// Manage the profiles of the keystore
+    #[command(subcommand)]
+    Profiles(ProfilesCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum ProfilesCommand {
+    /// List the stored profiles
+    List,
+    /// Delete a stored profile
+    Delete {
+        /// Name of the profile
+        name: String,
+    },
+}
+
+/// SavedSession is the session stored by login for logout and whoami.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedSession {
+    server: String,
+    #[serde(default)]
+    realm: String,
+    user: String,
+    session_id: String,
+    expires_at: u64,
+}
 
-    let addr = env::var("SERVER_ADDR").unwrap_or_else(|_| "http://127.0.0.1:50051".to_string());
+/// Output is the result of a command, both for scripts and for humans.
+struct Output {
+    json: Value,
+    text: String,
+}
 
-    let mut client = AuthClient::connect(addr).await.expect("Failed to connect to the server");
+/// CliError is an error reported to the user, with the gRPC status code if it was returned by the server.
+#[derive(Debug)]
+struct CliError {
+    message: String,
+    code: Option<Code>,
+}
 
-    let user_id: String = "Pavel".to_string();
-    let secret = BigUint::from(123456u32); // Hard-coded for simplicity, could use a random number too
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "{} ({:?})", self.message, code),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
 
-    let y1 = g.modpow(&secret, &p); // g^secret mod p
-    let y2 = h.modpow(&secret, &p);  // h^secret mod p
+impl From<SdkError> for CliError {
+    fn from(err: SdkError) -> Self {
+        let message = match &err {
+            SdkError::Connect(err) => format!("could not connect to the server: {}", error_chain(err)),
+            SdkError::Rpc(status) => status.message().to_string(),
+            err => err.to_string(),
+        };
+        CliError { message, code: err.code() }
+    }
+}
+
+impl From<String> for CliError {
+    fn from(message: String) -> Self {
+        CliError { message, code: None }
+    }
+}
+
+/// error_chain formats an error together with its sources, which carry the details of transport errors.
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message = format!("{}: {}", message, err);
+        source = err.source();
+    }
+    message
+}
+
+/// connect connects to the server, using TLS for https:// addresses, and makes all requests in the realm.
+async fn connect(cli: &Cli, server: &str, realm: &str) -> Result<AuthClientSdk, CliError> {
+    let mut endpoint =
+        Endpoint::from_shared(server.to_string()).map_err(|err| format!("invalid server address {}: {}", server, err))?;
+    if server.starts_with("https://") {
+        let cert_and_key = cli.tls.client_cert.as_deref().zip(cli.tls.client_key.as_deref());
+        let tls_config = tls::client_config(cli.tls.ca_file.as_deref(), cert_and_key, cli.tls.tls_domain.as_deref())
+            .map_err(|err| format!("could not load TLS certificates: {}", err))?;
+        endpoint = endpoint.tls_config(tls_config).map_err(|err| error_chain(&err))?;
+    }
+    let sdk = AuthClientSdk::connect(endpoint).await.map_err(|err| match err {
+        SdkError::Connect(err) => CliError::from(format!("could not connect to {}: {}", server, error_chain(&err))),
+        err => err.into(),
+    })?;
+    sdk.with_realm(realm).map_err(|_| format!("invalid realm {:?}", realm).into())
+}
+
+/// server returns the server given on the command line, or else the one of the profile.
+fn server(cli: &Cli, profile: Option<&Profile>) -> String {
+    cli.server
+        .clone()
+        .or_else(|| profile.map(|profile| profile.server_url.clone()))
+        .unwrap_or_else(|| DEFAULT_SERVER.to_string())
+}
+
+/// realm returns the realm given on the command line, or else the default realm.
+fn realm(cli: &Cli) -> &str {
+    cli.realm.as_deref().unwrap_or_default()
+}
 
-    let register_request = RegisterRequest {
-        user: user_id.clone(),
-        y1: y1.to_bytes_be(),
-        y2: y2.to_bytes_be(),
+/// group returns the id of the profile's group or the one given on the command line, and the group's parameters.
+fn group<'a>(cli: &'a Cli, profile: Option<&'a Profile>) -> Result<(&'a str, ZKP), CliError> {
+    let group_id = match (profile, cli.group.as_deref()) {
+        (Some(profile), Some(group_id)) if profile.group_id != group_id => {
+            return Err(format!("profile uses the group {}, not {}", profile.group_id, group_id).into());
+        }
+        (Some(profile), _) => &profile.group_id,
+        (None, group_id) => group_id.unwrap_or(DEFAULT_GROUP_ID),
     };
+    let (g, h, p, q) = ::zkp_auth::group_cfg(group_id)
+        .ok_or_else(|| format!("unsupported group {}, expected one of {}", group_id, GROUP_IDS.join(", ")))?;
+    Ok((group_id, ZKP { g, h, p, q }))
+}
+
+/// user returns the username of the profile or the one given on the command line.
+fn user<'a>(cli: &'a Cli, profile: Option<&'a Profile>) -> Result<&'a str, CliError> {
+    match (profile, cli.user.as_deref()) {
+        (Some(profile), Some(user)) if profile.username != user => {
+            Err(format!("profile belongs to {}, not {}", profile.username, user).into())
+        }
+        (Some(profile), _) => Ok(&profile.username),
+        (None, Some(user)) => Ok(user),
+        (None, None) => Err("a username is required, pass --user or set ZKPAUTH_USER".to_string().into()),
+    }
+}
 
-    client.register(register_request).await.expect("Failed to register user");
-    println!("Registration successful.");
+/// password reads a password from the environment variable env_key, stdin or the terminal, in that order.
+/// Passwords typed on the terminal have to be confirmed if confirm is set.
+fn password(cli: &Cli, prompt: &str, env_key: &str, confirm: bool) -> Result<String, CliError> {
+    if let Ok(password) = env::var(env_key) {
+        return Ok(password);
+    }
+    if cli.password_stdin {
+        let mut line = String::new();
+        io::stdin().lock().read_line(&mut line).map_err(|err| format!("could not read password: {}", err))?;
+        return Ok(line.trim_end_matches(['\r', '\n']).to_string());
+    }
+    let password = rpassword::prompt_password(prompt).map_err(|err| format!("could not read password: {}", err))?;
+    if confirm && rpassword::prompt_password("Repeat password: ").map_err(|err| err.to_string())? != password {
+        return Err("passwords do not match".to_string().into());
+    }
+    Ok(password)
+}
+
+/// keystore opens the keystore in the profiles directory of the client's state.
+fn keystore() -> Result<Keystore, CliError> {
+    Ok(Keystore::new(state_dir()?.join("profiles")))
+}
 
-    let k = gen_random_number_below(&BigUint::from(1_000_000u32));
-    let r1 = g.modpow(&k, &p);
-    let r2 = h.modpow(&k, &p);
+/// UnlockedProfile is a decrypted keystore profile together with what is needed to store it again.
+struct UnlockedProfile {
+    name: String,
+    passphrase: String,
+    profile: Profile,
+}
 
-    let challenge_request = AuthenticationChallengeRequest {
-        user: user_id.clone(),
-        r1: r1.to_bytes_be(),
-        r2: r2.to_bytes_be(),
+/// unlock decrypts the profile given on the command line, if any.
+fn unlock(cli: &Cli) -> Result<Option<UnlockedProfile>, CliError> {
+    let Some(name) = &cli.profile else {
+        return Ok(None);
     };
+    let passphrase = password(cli, "Keystore passphrase: ", "ZKPAUTH_PASSPHRASE", false)?;
+    let profile = keystore()?.unlock(name, &passphrase).map_err(|err| err.to_string())?;
+    Ok(Some(UnlockedProfile { name: name.clone(), passphrase, profile }))
+}
+
+/// secret returns the secret of the profile, or loads it from the key file, or derives it from the user's password.
+fn secret(cli: &Cli, zkp: &ZKP, user: &str, profile: Option<&Profile>, confirm: bool) -> Result<BigUint, CliError> {
+    if let Some(profile) = profile {
+        return Ok(profile.secret.clone());
+    }
+    match &cli.keyfile {
+        Some(path) => secret::read_keyfile(path)
+            .map_err(|err| format!("could not read key file {}: {}", path.display(), err).into()),
+        None => Ok(secret::from_password(user, &password(cli, "Password: ", "ZKPAUTH_PASSWORD", confirm)?, &zkp.q)),
+    }
+}
 
-    let res = client.authentication_challenge(challenge_request).await.expect("Could not request challenge from server").into_inner();
-    println!("Received challenge from server.");
+/// state_dir is where the client keeps its state, `$ZKPAUTH_HOME` or `~/.zkpauth`.
+fn state_dir() -> Result<PathBuf, CliError> {
+    if let Some(dir) = env::var_os("ZKPAUTH_HOME") {
+        return Ok(dir.into());
+    }
+    env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".zkpauth"))
+        .ok_or_else(|| "cannot find the home directory, set ZKPAUTH_HOME".to_string().into())
+}
 
-    let s = zkp.solve(&k, &BigUint::from_bytes_be(&res.c), &secret);
+/// session_path is the file the current session is saved in.
+fn session_path() -> Result<PathBuf, CliError> {
+    Ok(state_dir()?.join("session.json"))
+}
 
-    let answer_request = AuthenticationAnswerRequest {
-        auth_id: res.auth_id,
-        s: s.to_bytes_be(),
+/// save_session stores the session in a file only the user can read.
+fn save_session(session: &SavedSession) -> Result<(), CliError> {
+    let path = session_path()?;
+    let write = || -> io::Result<()> {
+        fs::create_dir_all(path.parent().expect("session path has a parent"))?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        serde_json::to_writer_pretty(options.open(&path)?, session)?;
+        Ok(())
     };
+    write().map_err(|err| format!("could not save session to {}: {}", path.display(), err).into())
+}
+
+/// load_session reads the session saved by login.
+fn load_session() -> Result<SavedSession, CliError> {
+    let path = session_path()?;
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err("not logged in".to_string().into()),
+        Err(err) => return Err(format!("could not read session from {}: {}", path.display(), err).into()),
+    };
+    serde_json::from_str(&contents).map_err(|err| format!("invalid session file {}: {}", path.display(), err).into())
+}
+
+/// run executes the command given on the command line.
+async fn run(cli: &Cli) -> Result<Output, CliError> {
+    match &cli.command {
+        Command::Keygen => {
+            let (group_id, zkp) = group(cli, None)?;
+            let x = gen_random_number_below(&zkp.q);
+            let PublicKeys { y1, y2 } = PublicKeys::from_secret(&zkp, &x);
+            let public_keys = json!({ "y1": y1.to_str_radix(16), "y2": y2.to_str_radix(16) });
+            if let Some(name) = &cli.profile {
+                let profile = Profile {
+                    secret: x,
+                    group_id: group_id.to_string(),
+                    server_url: server(cli, None),
+                    username: user(cli, None)?.to_string(),
+                    next_secret: None,
+                };
+                let passphrase = password(cli, "New keystore passphrase: ", "ZKPAUTH_PASSPHRASE", true)?;
+                keystore()?.create(name, &profile, &passphrase).map_err(|err| err.to_string())?;
+                return Ok(Output {
+                    json: json!({ "profile": name, "user": profile.username, "server": profile.server_url, "public_keys": public_keys }),
+                    text: format!("Stored a new secret for {} in profile {}", profile.username, name),
+                });
+            }
+
+            let path = cli.keyfile.as_ref().ok_or_else(|| "keygen needs --keyfile or --profile to store the secret in".to_string())?;
+            secret::write_keyfile(path, &x)
+                .map_err(|err| format!("could not write key file {}: {}", path.display(), err))?;
+            Ok(Output {
+                json: json!({ "keyfile": path, "public_keys": public_keys }),
+                text: format!("Stored a new secret in {}", path.display()),
+            })
+        }
+        Command::Register => {
+            let unlocked = unlock(cli)?;
+            let profile = unlocked.as_ref().map(|unlocked| &unlocked.profile);
+            let user = user(cli, profile)?;
+            let (_, zkp) = group(cli, profile)?;
+            let x = secret(cli, &zkp, user, profile, true)?;
+            connect(cli, &server(cli, profile), realm(cli)).await?.with_group(zkp).register(user, &x).await?;
+            Ok(Output { json: json!({ "user": user, "registered": true }), text: format!("Registered {}", user) })
+        }
+        Command::Login { register: register_first } => {
+            let unlocked = unlock(cli)?;
+            let profile = unlocked.as_ref().map(|unlocked| &unlocked.profile);
+            let user = user(cli, profile)?;
+            let (_, zkp) = group(cli, profile)?;
+            let x = secret(cli, &zkp, user, profile, false)?;
+            let server = server(cli, profile);
+            let sdk = connect(cli, &server, realm(cli)).await?.with_group(zkp);
+            let mut registered = false;
+            if *register_first {
+                match sdk.register(user, &x).await {
+                    Ok(()) => registered = true,
+                    Err(SdkError::AlreadyRegistered) => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
 
-    let res = client.verify_authentication(answer_request).await.expect("Could not verify authentication on server").into_inner();
+            let session = sdk.login(user, &x).await?;
+            let expires_at = unix_seconds(session.expires_at);
+            let realm = realm(cli).to_string();
+            save_session(&SavedSession { server, realm, user: session.user, session_id: session.id.clone(), expires_at })?;
+            Ok(Output {
+                json: json!({
+                    "user": user,
+                    "registered": registered,
+                    "session_id": session.id,
+                    "expires_at": expires_at,
+                }),
+                text: format!("Logged in as {}. Session ID: {}", user, session.id),
+            })
+        }
+        Command::Rotate { new_keyfile } => {
+            let unlocked = unlock(cli)?;
+            let profile = unlocked.as_ref().map(|unlocked| &unlocked.profile);
+            let user = user(cli, profile)?;
+            let (_, zkp) = group(cli, profile)?;
+            let x = secret(cli, &zkp, user, profile, false)?;
+            // next_secret is left behind by a rotation which was interrupted before the profile was updated
+            let next_secret = profile.and_then(|profile| profile.next_secret.clone());
+            let new_x = match (new_keyfile, &next_secret) {
+                (Some(path), _) => secret::read_keyfile(path)
+                    .map_err(|err| format!("could not read key file {}: {}", path.display(), err))?,
+                (None, Some(next_secret)) => next_secret.clone(),
+                (None, None) if profile.is_some() => gen_random_number_below(&zkp.q),
+                (None, None) => {
+                    let new_password = password(cli, "New password: ", "ZKPAUTH_NEW_PASSWORD", true)?;
+                    secret::from_password(user, &new_password, &zkp.q)
+                }
+            };
 
-    println!("Successfully logged in! Session ID: {}", res.session_id);
+            // The new secret is stored before the server switches to it, so that it is not lost if the client dies or
+            // the connection drops before the answer arrives.
+            if let Some(UnlockedProfile { name, passphrase, profile }) = &unlocked {
+                let pending = Profile { next_secret: Some(new_x.clone()), ..profile.clone() };
+                keystore()?
+                    .update(name, &pending, passphrase)
+                    .map_err(|err| format!("could not store the new secret in profile {}: {}", name, err))?;
+            }
+
+            let sdk = connect(cli, &server(cli, profile), realm(cli)).await?.with_group(zkp);
+            let mut rotated = sdk.rotate_keys(user, &x, &new_x).await;
+            if let (Err(_), Some(next_secret)) = (&rotated, &next_secret) {
+                // the server may have switched to the secret of the interrupted rotation already
+                if sdk.rotate_keys(user, next_secret, &new_x).await.is_ok() {
+                    rotated = Ok(());
+                }
+            }
+            rotated?;
+
+            if let Some(UnlockedProfile { name, passphrase, profile }) = &unlocked {
+                let rotated = Profile { secret: new_x, next_secret: None, ..profile.clone() };
+                keystore()?
+                    .update(name, &rotated, passphrase)
+                    .map_err(|err| format!("the keys were rotated but profile {} could not be updated: {}", name, err))?;
+            }
+            Ok(Output { json: json!({ "user": user, "rotated": true }), text: format!("Rotated the keys of {}", user) })
+        }
+        Command::Logout => {
+            let session = load_session()?;
+            let realm = cli.realm.as_deref().unwrap_or(&session.realm);
+            let sdk = connect(cli, cli.server.as_deref().unwrap_or(&session.server), realm).await?;
+            sdk.logout(&session.session_id).await?;
+            fs::remove_file(session_path()?).map_err(|err| format!("could not remove session: {}", err))?;
+            Ok(Output { json: json!({ "user": session.user, "logged_out": true }), text: format!("Logged out {}", session.user) })
+        }
+        Command::Whoami => {
+            let session = load_session()?;
+            let realm = cli.realm.as_deref().unwrap_or(&session.realm);
+            let sdk = connect(cli, cli.server.as_deref().unwrap_or(&session.server), realm).await?;
+            let info = sdk.validate_session(&session.session_id).await?;
+            Ok(Output {
+                json: json!({
+                    "user": info.user,
+                    "issued_at": unix_seconds(info.issued_at),
+                    "expires_at": unix_seconds(info.expires_at),
+                    "peer_addr": info.client.peer_addr,
+                    "user_agent": info.client.user_agent,
+                }),
+                text: info.user,
+            })
+        }
+        Command::Profiles(ProfilesCommand::List) => {
+            let names = keystore()?.list().map_err(|err| err.to_string())?;
+            Ok(Output { json: json!({ "profiles": names }), text: names.join("\n") })
+        }
+        Command::Profiles(ProfilesCommand::Delete { name }) => {
+            keystore()?.delete(name).map_err(|err| err.to_string())?;
+            Ok(Output { json: json!({ "profile": name, "deleted": true }), text: format!("Deleted profile {}", name) })
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli).await {
+        Ok(output) => {
+            if cli.json {
+                println!("{}", output.json);
+            } else {
+                println!("{}", output.text);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            if cli.json {
+                println!("{}", json!({ "error": err.message, "code": err.code.map(|code| format!("{:?}", code)) }));
+            } else {
+                eprintln!("Error: {}", err);
+            }
+            ExitCode::FAILURE
+        }
+    }
 }
@@ -0,0 +1,691 @@
+use crate::{ratelimit::RateLimitConfig, DEFAULT_GROUP_ID, GROUP_IDS};
+use serde::Deserialize;
+use std::{collections::BTreeMap, fmt, fs, net::SocketAddr, path::PathBuf, time::Duration};
+
+/// How long an issued challenge can be answered unless configured otherwise.
+pub const DEFAULT_CHALLENGE_TTL: Duration = Duration::from_secs(120);
+/// How long a session stays valid unless configured otherwise.
+pub const DEFAULT_SESSION_TTL: Duration = Duration::from_secs(3600);
+/// How often the reaper removes expired challenges and sessions unless configured otherwise.
+pub const DEFAULT_REAP_INTERVAL: Duration = Duration::from_secs(30);
+/// How often the token key directory is checked for a new key unless configured otherwise.
+pub const DEFAULT_KEY_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+/// How long the server keeps serving pending challenges after it has been asked to stop unless configured otherwise.
+pub const DEFAULT_SHUTDOWN_DRAIN: Duration = Duration::from_secs(15);
+
+/// The storage backends the server can keep its users, challenges and sessions in.
+pub const STORAGE_BACKENDS: &[&str] = &["memory"];
+/// The formats the server can write its logs in.
+pub const LOG_FORMATS: &[&str] = &["text", "json"];
+
+/// ConfigError is returned when the server configuration cannot be read or is not valid.
+#[derive(Debug)]
+pub enum ConfigError {
+    // Io means the configuration file could not be read
+    Io(std::io::Error),
+    // Parse means the configuration file is not valid TOML or has unknown or mistyped settings
+    Parse(toml::de::Error),
+    // Invalid means a setting has a value the server cannot work with
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read configuration: {}", err),
+            ConfigError::Parse(err) => write!(f, "could not parse configuration: {}", err),
+            ConfigError::Invalid(msg) => write!(f, "invalid configuration: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(err: std::io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(err: toml::de::Error) -> Self {
+        ConfigError::Parse(err)
+    }
+}
+
+/// ServerConfig holds the settings of the server. Settings missing from the configuration file keep their defaults.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // listen_addr is the address the gRPC server listens on
+    pub listen_addr: String,
+    // http_listen_addr is the address the HTTP server publishing the JWKS document listens on
+    pub http_listen_addr: String,
+    // grpc_web makes the gRPC server also accept gRPC-Web calls from browsers
+    pub grpc_web: bool,
+    // rest_api makes the HTTP server also serve the Auth service as JSON under /v1
+    pub rest_api: bool,
+    // cors_allowed_origins are the origins, e.g. https://app.example.com, of the web pages allowed to call the gRPC-Web
+    // and JSON APIs from browsers; "*" allows any origin and none allows only the origin of the servers themselves
+    pub cors_allowed_origins: Vec<String>,
+    // group identifies the group parameters users prove knowledge of their secret in
+    pub group: String,
+    // challenge_ttl_secs is how long a challenge can be answered after it has been issued
+    pub challenge_ttl_secs: u64,
+    // session_ttl_secs is how long a session stays valid after it has been created or refreshed
+    pub session_ttl_secs: u64,
+    // reap_interval_secs is how often expired challenges and sessions are removed
+    pub reap_interval_secs: u64,
+    // shutdown_drain_secs is how long the server waits for pending challenges to be answered before it stops
+    pub shutdown_drain_secs: u64,
+    // audit_log is the file the audit log is appended to; nothing is audited unless it is set
+    pub audit_log: Option<PathBuf>,
+    // audit_key_file holds the key the hashes of the audit log are keyed with, without which whoever can write the
+    // log can also truncate or rewrite it unnoticed
+    pub audit_key_file: Option<PathBuf>,
+    // admin_token_file holds the bearer token of the Admin service; the service is not served unless it is set
+    pub admin_token_file: Option<PathBuf>,
+    pub storage: StorageConfig,
+    // tls enables TLS on the gRPC server when present
+    pub tls: Option<TlsConfig>,
+    pub session_tokens: SessionTokenConfig,
+    pub rate_limits: RateLimitSettings,
+    pub logging: LoggingConfig,
+    // realms are the tenants served next to the default realm by their id; the settings above are the ones of the
+    // default realm
+    pub realms: BTreeMap<String, RealmConfig>,
+}
+
+/// StorageConfig selects where the server keeps its state.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    // backend is one of STORAGE_BACKENDS
+    pub backend: String,
+}
+
+/// TlsConfig points at the certificate and key of the server and optionally at the CA client certificates must be
+/// signed by.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+    pub cert_file: PathBuf,
+    pub key_file: PathBuf,
+    // client_ca_file enables mutual TLS when set
+    #[serde(default)]
+    pub client_ca_file: Option<PathBuf>,
+}
+
+/// SessionTokenConfig enables signed session tokens, either with a single key or a directory of rotated keys.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionTokenConfig {
+    // key_file is a PEM private key signing all tokens
+    pub key_file: Option<PathBuf>,
+    // key_id is the kid of key_file; it defaults to the file name without extension
+    pub key_id: Option<String>,
+    // key_dir is a directory of PEM private keys whose greatest key id signs new tokens
+    pub key_dir: Option<PathBuf>,
+    // reload_interval_secs is how often key_dir is checked for a new key
+    pub reload_interval_secs: u64,
+}
+
+/// RateLimitSettings is the configuration file representation of RateLimitConfig.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitSettings {
+    pub peer_burst: u32,
+    pub peer_per_sec: f64,
+    pub user_burst: u32,
+    pub user_per_sec: f64,
+    pub failed_proof_backoff_secs: u64,
+    pub failed_proof_backoff_max_secs: u64,
+    pub lockout_threshold: u32,
+    pub lockout_secs: u64,
+}
+
+/// RateLimitOverrides are the rate limits a realm sets for itself. The ones it leaves out are the ones of the default
+/// realm. The limits per peer address apply to the server as a whole, so a realm cannot set them.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitOverrides {
+    pub user_burst: Option<u32>,
+    pub user_per_sec: Option<f64>,
+    pub failed_proof_backoff_secs: Option<u64>,
+    pub failed_proof_backoff_max_secs: Option<u64>,
+    pub lockout_threshold: Option<u32>,
+    pub lockout_secs: Option<u64>,
+}
+
+impl RateLimitOverrides {
+    /// apply returns the limits with the overrides applied.
+    pub fn apply(&self, limits: &RateLimitSettings) -> RateLimitSettings {
+        RateLimitSettings {
+            peer_burst: limits.peer_burst,
+            peer_per_sec: limits.peer_per_sec,
+            user_burst: self.user_burst.unwrap_or(limits.user_burst),
+            user_per_sec: self.user_per_sec.unwrap_or(limits.user_per_sec),
+            failed_proof_backoff_secs: self.failed_proof_backoff_secs.unwrap_or(limits.failed_proof_backoff_secs),
+            failed_proof_backoff_max_secs: self.failed_proof_backoff_max_secs.unwrap_or(limits.failed_proof_backoff_max_secs),
+            lockout_threshold: self.lockout_threshold.unwrap_or(limits.lockout_threshold),
+            lockout_secs: self.lockout_secs.unwrap_or(limits.lockout_secs),
+        }
+    }
+}
+
+/// RealmConfig holds the settings in which a realm differs from the default realm.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RealmConfig {
+    // group identifies the group parameters the users of the realm prove knowledge of their secret in; it is the
+    // group of the default realm unless set
+    pub group: Option<String>,
+    // rate_limits limit the challenges of the users of the realm; the limits left out are the ones of the default
+    // realm
+    pub rate_limits: RateLimitOverrides,
+}
+
+/// LoggingConfig controls what the server logs.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    // level is a log filter such as "info" or "warn,zkpauth_server=debug"
+    pub level: String,
+    // format is one of LOG_FORMATS
+    pub format: String,
+    // otlp_endpoint is the OTLP/gRPC collector spans are exported to, e.g. "http://localhost:4317"; spans are not
+    // exported unless it is set
+    pub otlp_endpoint: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen_addr: "127.0.0.1:50051".to_string(),
+            http_listen_addr: "127.0.0.1:8080".to_string(),
+            grpc_web: false,
+            rest_api: false,
+            cors_allowed_origins: Vec::new(),
+            group: DEFAULT_GROUP_ID.to_string(),
+            challenge_ttl_secs: DEFAULT_CHALLENGE_TTL.as_secs(),
+            session_ttl_secs: DEFAULT_SESSION_TTL.as_secs(),
+            reap_interval_secs: DEFAULT_REAP_INTERVAL.as_secs(),
+            shutdown_drain_secs: DEFAULT_SHUTDOWN_DRAIN.as_secs(),
+            audit_log: None,
+            audit_key_file: None,
+            admin_token_file: None,
+            storage: StorageConfig::default(),
+            tls: None,
+            session_tokens: SessionTokenConfig::default(),
+            rate_limits: RateLimitSettings::default(),
+            logging: LoggingConfig::default(),
+            realms: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig { backend: STORAGE_BACKENDS[0].to_string() }
+    }
+}
+
+impl Default for SessionTokenConfig {
+    fn default() -> Self {
+        SessionTokenConfig {
+            key_file: None,
+            key_id: None,
+            key_dir: None,
+            reload_interval_secs: DEFAULT_KEY_RELOAD_INTERVAL.as_secs(),
+        }
+    }
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        let defaults = RateLimitConfig::default();
+        RateLimitSettings {
+            peer_burst: defaults.peer_burst,
+            peer_per_sec: defaults.peer_per_second,
+            user_burst: defaults.user_burst,
+            user_per_sec: defaults.user_per_second,
+            failed_proof_backoff_secs: defaults.backoff_base.as_secs(),
+            failed_proof_backoff_max_secs: defaults.backoff_max.as_secs(),
+            lockout_threshold: defaults.lockout_threshold,
+            lockout_secs: defaults.lockout_duration.as_secs(),
+        }
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig { level: "info".to_string(), format: LOG_FORMATS[0].to_string(), otlp_endpoint: None }
+    }
+}
+
+impl RateLimitSettings {
+    /// rate_limit_config converts the settings to the configuration of the rate limiter.
+    pub fn rate_limit_config(&self) -> RateLimitConfig {
+        RateLimitConfig {
+            peer_burst: self.peer_burst,
+            peer_per_second: self.peer_per_sec,
+            user_burst: self.user_burst,
+            user_per_second: self.user_per_sec,
+            backoff_base: Duration::from_secs(self.failed_proof_backoff_secs),
+            backoff_max: Duration::from_secs(self.failed_proof_backoff_max_secs),
+            lockout_threshold: self.lockout_threshold,
+            lockout_duration: Duration::from_secs(self.lockout_secs),
+        }
+    }
+
+    /// validate checks that the limits can be enforced.
+    fn validate(&self) -> Result<(), String> {
+        if self.peer_burst == 0 || self.user_burst == 0 {
+            return Err("rate limit bursts must be greater than zero".to_string());
+        }
+        if !(self.peer_per_sec > 0.0 && self.user_per_sec > 0.0) {
+            return Err("rate limit rates must be greater than zero".to_string());
+        }
+        if self.failed_proof_backoff_secs > self.failed_proof_backoff_max_secs {
+            return Err("failed_proof_backoff_secs cannot be greater than failed_proof_backoff_max_secs".to_string());
+        }
+        // A threshold of 0 would lock users out after their first failed proof, and a lockout of 0 seconds would let
+        // them keep guessing without waiting.
+        if self.lockout_threshold == 0 || self.lockout_secs == 0 {
+            return Err("lockout_threshold and lockout_secs must be greater than zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// is_realm_id returns true if id can name a realm: it is not empty and consists of lowercase ASCII letters, digits,
+/// '-' and '_', so that it can be sent as metadata.
+pub fn is_realm_id(id: &str) -> bool {
+    !id.is_empty() && id.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// is_origin returns true if origin is the scheme, host and optional port of a web page, as browsers send them in the
+/// Origin header.
+fn is_origin(origin: &str) -> bool {
+    let Some((scheme, authority)) = origin.split_once("://") else {
+        return false;
+    };
+    matches!(scheme, "http" | "https")
+        && !authority.is_empty()
+        && authority.bytes().all(|b| b.is_ascii_alphanumeric() || b"-.:[]".contains(&b))
+}
+
+impl ServerConfig {
+    /// from_toml parses a configuration file's contents.
+    pub fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    /// load reads the configuration file at the given path.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    /// apply_env overrides settings with the environment variables returned by lookup, which is usually
+    /// `|key| std::env::var(key).ok()`.
+    pub fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        let string = |key: &str, value: &mut String| {
+            if let Some(v) = lookup(key) {
+                *value = v;
+            }
+        };
+        string("LISTEN_ADDR", &mut self.listen_addr);
+        string("HTTP_LISTEN_ADDR", &mut self.http_listen_addr);
+        string("GROUP", &mut self.group);
+        string("STORAGE_BACKEND", &mut self.storage.backend);
+        string("LOG_LEVEL", &mut self.logging.level);
+        string("LOG_FORMAT", &mut self.logging.format);
+        if let Some(origins) = lookup("CORS_ALLOWED_ORIGINS") {
+            self.cors_allowed_origins =
+                origins.split(',').map(str::trim).filter(|origin| !origin.is_empty()).map(str::to_string).collect();
+        }
+        if let Some(endpoint) = lookup("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            self.logging.otlp_endpoint = Some(endpoint).filter(|endpoint| !endpoint.is_empty());
+        }
+
+        let parse = |key: &str, value: &mut dyn FnMut(&str) -> bool| -> Result<(), ConfigError> {
+            match lookup(key) {
+                Some(v) if !value(&v) => Err(ConfigError::Invalid(format!("{}: {}", key, v))),
+                _ => Ok(()),
+            }
+        };
+        parse("GRPC_WEB", &mut |v| set(v, &mut self.grpc_web))?;
+        parse("REST_API", &mut |v| set(v, &mut self.rest_api))?;
+        parse("CHALLENGE_TTL_SECS", &mut |v| set(v, &mut self.challenge_ttl_secs))?;
+        parse("SESSION_TTL_SECS", &mut |v| set(v, &mut self.session_ttl_secs))?;
+        parse("REAP_INTERVAL_SECS", &mut |v| set(v, &mut self.reap_interval_secs))?;
+        parse("SHUTDOWN_DRAIN_SECS", &mut |v| set(v, &mut self.shutdown_drain_secs))?;
+        let limits = &mut self.rate_limits;
+        parse("RATE_LIMIT_PEER_BURST", &mut |v| set(v, &mut limits.peer_burst))?;
+        parse("RATE_LIMIT_PEER_PER_SEC", &mut |v| set(v, &mut limits.peer_per_sec))?;
+        parse("RATE_LIMIT_USER_BURST", &mut |v| set(v, &mut limits.user_burst))?;
+        parse("RATE_LIMIT_USER_PER_SEC", &mut |v| set(v, &mut limits.user_per_sec))?;
+        parse("FAILED_PROOF_BACKOFF_SECS", &mut |v| set(v, &mut limits.failed_proof_backoff_secs))?;
+        parse("FAILED_PROOF_BACKOFF_MAX_SECS", &mut |v| set(v, &mut limits.failed_proof_backoff_max_secs))?;
+        parse("LOCKOUT_THRESHOLD", &mut |v| set(v, &mut limits.lockout_threshold))?;
+        parse("LOCKOUT_SECS", &mut |v| set(v, &mut limits.lockout_secs))?;
+
+        if let Some(audit_log) = lookup("AUDIT_LOG") {
+            self.audit_log = Some(audit_log.into()).filter(|path: &PathBuf| !path.as_os_str().is_empty());
+        }
+        if let Some(audit_key_file) = lookup("AUDIT_KEY_FILE") {
+            self.audit_key_file = Some(audit_key_file.into()).filter(|path: &PathBuf| !path.as_os_str().is_empty());
+        }
+        if let Some(admin_token_file) = lookup("ADMIN_TOKEN_FILE") {
+            self.admin_token_file = Some(admin_token_file.into()).filter(|path: &PathBuf| !path.as_os_str().is_empty());
+        }
+
+        let tokens = &mut self.session_tokens;
+        parse("SESSION_TOKEN_KEY_RELOAD_SECS", &mut |v| set(v, &mut tokens.reload_interval_secs))?;
+        if let Some(dir) = lookup("SESSION_TOKEN_KEY_DIR") {
+            tokens.key_dir = Some(dir.into());
+            tokens.key_file = None;
+        }
+        if let Some(file) = lookup("SESSION_TOKEN_KEY_FILE") {
+            tokens.key_file = Some(file.into());
+            tokens.key_dir = None;
+        }
+        if let Some(key_id) = lookup("SESSION_TOKEN_KEY_ID") {
+            tokens.key_id = Some(key_id);
+        }
+
+        match (lookup("TLS_CERT_FILE"), lookup("TLS_KEY_FILE")) {
+            (Some(cert_file), Some(key_file)) => {
+                let client_ca_file = self.tls.take().and_then(|tls| tls.client_ca_file);
+                self.tls = Some(TlsConfig { cert_file: cert_file.into(), key_file: key_file.into(), client_ca_file });
+            }
+            (None, None) => {}
+            _ => return Err(ConfigError::Invalid("TLS_CERT_FILE and TLS_KEY_FILE must be set together".to_string())),
+        }
+        if let Some(client_ca_file) = lookup("TLS_CLIENT_CA_FILE") {
+            match &mut self.tls {
+                Some(tls) => tls.client_ca_file = Some(client_ca_file.into()),
+                None => return Err(ConfigError::Invalid("TLS_CLIENT_CA_FILE requires a TLS certificate".to_string())),
+            }
+        }
+        Ok(())
+    }
+
+    /// validate checks the settings which can be checked without touching the network or the file system.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |msg: String| Err(ConfigError::Invalid(msg));
+        for (name, addr) in [("listen_addr", &self.listen_addr), ("http_listen_addr", &self.http_listen_addr)] {
+            if addr.parse::<SocketAddr>().is_err() {
+                return invalid(format!("{} is not a socket address: {}", name, addr));
+            }
+        }
+        for origin in &self.cors_allowed_origins {
+            let valid = if origin == "*" {
+                self.cors_allowed_origins.len() == 1
+            } else {
+                is_origin(origin)
+            };
+            if !valid {
+                return invalid(format!("invalid CORS origin {:?}, expected \"*\" alone or origins like https://app.example.com", origin));
+            }
+        }
+        if !GROUP_IDS.contains(&self.group.as_str()) {
+            return invalid(format!("unsupported group {}, expected one of {:?}", self.group, GROUP_IDS));
+        }
+        if !STORAGE_BACKENDS.contains(&self.storage.backend.as_str()) {
+            return invalid(format!("unsupported storage backend {}, expected one of {:?}", self.storage.backend, STORAGE_BACKENDS));
+        }
+        if !LOG_FORMATS.contains(&self.logging.format.as_str()) {
+            return invalid(format!("unsupported log format {}, expected one of {:?}", self.logging.format, LOG_FORMATS));
+        }
+        if self.logging.otlp_endpoint.is_some() && !cfg!(feature = "otel") {
+            return invalid("logging.otlp_endpoint requires the otel feature".to_string());
+        }
+        for (name, secs) in [
+            ("challenge_ttl_secs", self.challenge_ttl_secs),
+            ("session_ttl_secs", self.session_ttl_secs),
+            ("reap_interval_secs", self.reap_interval_secs),
+            ("session_tokens.reload_interval_secs", self.session_tokens.reload_interval_secs),
+        ] {
+            if secs == 0 {
+                return invalid(format!("{} must be greater than zero", name));
+            }
+        }
+        if self.session_tokens.key_file.is_some() && self.session_tokens.key_dir.is_some() {
+            return invalid("session_tokens.key_file and session_tokens.key_dir cannot both be set".to_string());
+        }
+        self.rate_limits.validate().map_err(ConfigError::Invalid)?;
+        for (id, realm) in &self.realms {
+            if !is_realm_id(id) {
+                return invalid(format!("invalid realm id {:?}, expected lowercase letters, digits, '-' and '_'", id));
+            }
+            if let Some(group) = realm.group.as_deref().filter(|group| !GROUP_IDS.contains(group)) {
+                return invalid(format!("realms.{}: unsupported group {}, expected one of {:?}", id, group, GROUP_IDS));
+            }
+            self.realm_rate_limits(realm).validate().map_err(|msg| ConfigError::Invalid(format!("realms.{}: {}", id, msg)))?;
+        }
+        Ok(())
+    }
+
+    /// realm_rate_limits returns the rate limits of the realm: the ones of the default realm with the overrides of the
+    /// realm applied.
+    pub fn realm_rate_limits(&self, realm: &RealmConfig) -> RateLimitSettings {
+        realm.rate_limits.apply(&self.rate_limits)
+    }
+
+    /// challenge_ttl returns how long a challenge can be answered after it has been issued.
+    pub fn challenge_ttl(&self) -> Duration {
+        Duration::from_secs(self.challenge_ttl_secs)
+    }
+
+    /// session_ttl returns how long a session stays valid after it has been created or refreshed.
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.session_ttl_secs)
+    }
+
+    /// reap_interval returns how often expired challenges and sessions are removed.
+    pub fn reap_interval(&self) -> Duration {
+        Duration::from_secs(self.reap_interval_secs)
+    }
+
+    /// shutdown_drain returns how long the server waits for pending challenges to be answered before it stops.
+    pub fn shutdown_drain(&self) -> Duration {
+        Duration::from_secs(self.shutdown_drain_secs)
+    }
+}
+
+/// set parses value into target and returns false if it is not valid.
+fn set<T: std::str::FromStr>(value: &str, target: &mut T) -> bool {
+    match value.parse() {
+        Ok(parsed) => {
+            *target = parsed;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_defaults_are_valid() {
+        let config = ServerConfig::from_toml("").unwrap();
+        assert_eq!(config, ServerConfig::default());
+        config.validate().unwrap();
+        assert_eq!(config.rate_limits.rate_limit_config(), RateLimitConfig::default());
+    }
+
+    #[test]
+    fn test_example_config() {
+        let config = ServerConfig::load(concat!(env!("CARGO_MANIFEST_DIR"), "/config/zkpauth-server.toml")).unwrap();
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        let config = ServerConfig::from_toml(
+            r#"
+            listen_addr = "0.0.0.0:50051"
+            challenge_ttl_secs = 30
+
+            [tls]
+            cert_file = "server.pem"
+            key_file = "server.key"
+
+            [rate_limits]
+            user_burst = 3
+
+            [realms.shop]
+            group = "rfc5114-2048-224"
+
+            [realms.blog.rate_limits]
+            user_burst = 10
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.listen_addr, "0.0.0.0:50051");
+        assert_eq!(config.challenge_ttl(), Duration::from_secs(30));
+        assert_eq!(config.session_ttl(), DEFAULT_SESSION_TTL);
+        let tls = config.tls.unwrap();
+        assert_eq!(tls.cert_file, PathBuf::from("server.pem"));
+        assert_eq!(tls.client_ca_file, None);
+        assert_eq!(config.rate_limits.user_burst, 3);
+        assert_eq!(config.rate_limits.peer_burst, RateLimitSettings::default().peer_burst);
+        assert_eq!(config.realms.keys().collect::<Vec<_>>(), ["blog", "shop"]);
+        assert_eq!(config.realms["shop"], RealmConfig { group: Some("rfc5114-2048-224".to_string()), ..Default::default() });
+        assert_eq!(config.realms["blog"].group, None);
+        assert_eq!(config.realms["blog"].rate_limits.user_burst, Some(10));
+
+        assert!(matches!(ServerConfig::from_toml("listen_adr = \"0.0.0.0:1\""), Err(ConfigError::Parse(_))));
+        assert!(matches!(ServerConfig::from_toml("[tls]\ncert_file = \"a.pem\""), Err(ConfigError::Parse(_))));
+    }
+
+    #[test]
+    fn test_realm_rate_limits() {
+        let mut config = ServerConfig::from_toml(
+            r#"
+            [rate_limits]
+            user_burst = 3
+            lockout_threshold = 7
+
+            [realms.blog.rate_limits]
+            user_burst = 10
+            failed_proof_backoff_secs = 2
+            "#,
+        )
+        .unwrap();
+        config.validate().unwrap();
+
+        // the limits the realm leaves out are the ones of the default realm, not the defaults
+        let limits = config.realm_rate_limits(&config.realms["blog"]);
+        assert_eq!(limits.user_burst, 10);
+        assert_eq!(limits.failed_proof_backoff_secs, 2);
+        assert_eq!(limits.lockout_threshold, 7);
+        assert_eq!(limits.user_per_sec, RateLimitSettings::default().user_per_sec);
+        // including the ones set in the environment
+        config.apply_env(|key| (key == "LOCKOUT_SECS").then(|| "60".to_string())).unwrap();
+        assert_eq!(config.realm_rate_limits(&config.realms["blog"]).lockout_secs, 60);
+
+        // the merged limits are validated
+        config.rate_limits.failed_proof_backoff_max_secs = 1;
+        config.rate_limits.failed_proof_backoff_secs = 1;
+        assert!(matches!(config.validate(), Err(ConfigError::Invalid(msg)) if msg.starts_with("realms.blog:")));
+
+        // the limits per peer address are the same for all realms
+        assert!(ServerConfig::from_toml("[realms.blog.rate_limits]\npeer_burst = 5").is_err());
+        assert!(ServerConfig::from_toml("[realms.blog.rate_limits]\npeer_per_sec = 5.0").is_err());
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let mut config = ServerConfig::from_toml("challenge_ttl_secs = 30\n[session_tokens]\nkey_dir = \"keys\"").unwrap();
+        let env: HashMap<&str, &str> = [
+            ("LISTEN_ADDR", "0.0.0.0:1234"),
+            ("CHALLENGE_TTL_SECS", "45"),
+            ("REST_API", "true"),
+            ("CORS_ALLOWED_ORIGINS", "https://app.example.com, http://localhost:3000"),
+            ("SHUTDOWN_DRAIN_SECS", "0"),
+            ("LOG_FORMAT", "json"),
+            ("AUDIT_LOG", "/var/log/zkpauth/audit.log"),
+            ("AUDIT_KEY_FILE", "/run/secrets/audit-key"),
+            ("ADMIN_TOKEN_FILE", "/run/secrets/admin-token"),
+            ("OTEL_EXPORTER_OTLP_ENDPOINT", "http://collector:4317"),
+            ("RATE_LIMIT_PEER_PER_SEC", "2.5"),
+            ("SESSION_TOKEN_KEY_FILE", "key.pem"),
+            ("TLS_CERT_FILE", "server.pem"),
+            ("TLS_KEY_FILE", "server.key"),
+        ]
+        .into();
+        config.apply_env(|key| env.get(key).map(|v| v.to_string())).unwrap();
+
+        assert_eq!(config.listen_addr, "0.0.0.0:1234");
+        assert_eq!(config.challenge_ttl_secs, 45);
+        assert!(config.rest_api && !config.grpc_web);
+        assert_eq!(config.cors_allowed_origins, ["https://app.example.com", "http://localhost:3000"]);
+        assert_eq!(config.shutdown_drain(), Duration::ZERO);
+        assert_eq!(config.logging.format, "json");
+        assert_eq!(config.audit_log, Some(PathBuf::from("/var/log/zkpauth/audit.log")));
+        assert_eq!(config.audit_key_file, Some(PathBuf::from("/run/secrets/audit-key")));
+        assert_eq!(config.admin_token_file, Some(PathBuf::from("/run/secrets/admin-token")));
+        assert_eq!(config.logging.otlp_endpoint.as_deref(), Some("http://collector:4317"));
+        assert_eq!(config.rate_limits.peer_per_sec, 2.5);
+        assert_eq!(config.session_tokens.key_file, Some(PathBuf::from("key.pem")));
+        assert_eq!(config.session_tokens.key_dir, None);
+        assert_eq!(config.tls.as_ref().map(|tls| tls.key_file.clone()), Some(PathBuf::from("server.key")));
+        config.validate().unwrap();
+
+        let result = config.apply_env(|key| (key == "SESSION_TTL_SECS").then(|| "soon".to_string()));
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+        let result = ServerConfig::default().apply_env(|key| (key == "TLS_CERT_FILE").then(|| "a.pem".to_string()));
+        assert!(matches!(result, Err(ConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_validate() {
+        let invalid = [
+            ServerConfig { listen_addr: "localhost".to_string(), ..Default::default() },
+            ServerConfig { group: "modp-2048".to_string(), ..Default::default() },
+            ServerConfig { storage: StorageConfig { backend: "redis".to_string() }, ..Default::default() },
+            ServerConfig { session_ttl_secs: 0, ..Default::default() },
+            ServerConfig {
+                session_tokens: SessionTokenConfig {
+                    key_file: Some("key.pem".into()),
+                    key_dir: Some("keys".into()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            ServerConfig { rate_limits: RateLimitSettings { user_per_sec: 0.0, ..Default::default() }, ..Default::default() },
+            ServerConfig { rate_limits: RateLimitSettings { lockout_threshold: 0, ..Default::default() }, ..Default::default() },
+            ServerConfig { rate_limits: RateLimitSettings { lockout_secs: 0, ..Default::default() }, ..Default::default() },
+            ServerConfig { logging: LoggingConfig { format: "xml".to_string(), ..Default::default() }, ..Default::default() },
+            ServerConfig { cors_allowed_origins: vec!["https://app.example.com/".to_string()], ..Default::default() },
+            ServerConfig { cors_allowed_origins: vec!["*".to_string(), "https://app.example.com".to_string()], ..Default::default() },
+            ServerConfig { realms: [("Shop".to_string(), RealmConfig::default())].into(), ..Default::default() },
+            ServerConfig { realms: [(String::new(), RealmConfig::default())].into(), ..Default::default() },
+            ServerConfig {
+                realms: [("shop".to_string(), RealmConfig { group: Some("rfc3526-2048".to_string()), ..Default::default() })].into(),
+                ..Default::default()
+            },
+            ServerConfig {
+                realms: [(
+                    "shop".to_string(),
+                    RealmConfig { rate_limits: RateLimitOverrides { user_burst: Some(0), ..Default::default() }, group: None },
+                )]
+                .into(),
+                ..Default::default()
+            },
+        ];
+        for config in invalid {
+            assert!(matches!(config.validate(), Err(ConfigError::Invalid(_))), "{:?}", config);
+        }
+        for origins in [&["*"][..], &["https://app.example.com", "http://localhost:3000", "http://[::1]:8000"]] {
+            let origins = origins.iter().map(|origin| origin.to_string()).collect();
+            ServerConfig { cors_allowed_origins: origins, ..Default::default() }.validate().unwrap();
+        }
+    }
+}
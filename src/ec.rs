@@ -0,0 +1,41 @@
+use k256::{ProjectivePoint, Scalar};
+
+/// public_key computes the public key y = x * G of the secret x, G being the generator of secp256k1.
+pub fn public_key(x: &Scalar) -> ProjectivePoint {
+    ProjectivePoint::GENERATOR * x
+}
+
+/// commitment computes the commitment r = k * G the prover sends before it is challenged, where k is a random
+/// nonce only the prover knows.
+pub fn commitment(k: &Scalar) -> ProjectivePoint {
+    ProjectivePoint::GENERATOR * k
+}
+
+/// solve is used by a prover to answer the challenge c with s = k + c * x.
+pub fn solve(k: &Scalar, c: &Scalar, x: &Scalar) -> Scalar {
+    k + c * x
+}
+
+/// verify is used by a verifier to check that s * G - c * y equals the commitment r.
+pub fn verify(r: &ProjectivePoint, y: &ProjectivePoint, c: &Scalar, s: &Scalar) -> bool {
+    ProjectivePoint::GENERATOR * s - y * c == *r
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_verify() {
+        let x = Scalar::from(6u32);
+        let k = Scalar::from(7u32);
+        let c = Scalar::from(4u32);
+
+        let y = public_key(&x);
+        let r = commitment(&k);
+        let s = solve(&k, &c, &x);
+        assert!(verify(&r, &y, &c, &s));
+        assert!(!verify(&r, &y, &c, &(s + Scalar::ONE)));
+        assert!(!verify(&r, &public_key(&Scalar::from(15u32)), &c, &s));
+    }
+}
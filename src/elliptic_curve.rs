@@ -1,16 +1,17 @@
 use k256::elliptic_curve::{sec1::ToEncodedPoint, Field};
-use k256::{ProjectivePoint, Scalar};
+use k256::Scalar;
 use rand::rngs::OsRng;
+use zkp_auth::ec;
 
 fn main() {
     // Generate a random private key for the prover
     let mut rng = OsRng;
     let sk = Scalar::random(&mut rng);
-    let pk = ProjectivePoint::GENERATOR * sk;
+    let pk = ec::public_key(&sk);
 
     // Prover generates a random nonce
     let nonce = Scalar::random(&mut rng);
-    let r = ProjectivePoint::GENERATOR * nonce;
+    let r = ec::commitment(&nonce);
 
     // Prover sends the commitment (r) to the verifier
     println!("Prover sends r: {:?}", r.to_encoded_point(false));
@@ -20,21 +21,18 @@ fn main() {
     println!("Verifier sends challenge: {:?}", challenge);
 
     // Prover computes the response
-    let response = nonce + challenge * sk;
+    let response = ec::solve(&nonce, &challenge, &sk);
     println!("Prover sends response: {:?}", response);
 
-    // Verifier computes r' = response * G - challenge * pk
-    let computed_r = ProjectivePoint::GENERATOR * response - pk * challenge;
-    println!("Verifier computes r': {:?}", computed_r.to_encoded_point(false));
-
-    // Verifier checks if r' == r
-    let valid = computed_r.to_affine().eq(&r.to_affine());
+    // Verifier checks if response * G - challenge * pk == r
+    let valid = ec::verify(&r, &pk, &challenge, &response);
     println!("Verification result: {:?}", valid);
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use k256::ProjectivePoint;
 
     #[test]
     fn test_zkp_authentication() {
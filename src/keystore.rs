@@ -0,0 +1,449 @@
+use argon2::{Algorithm, Argon2, Params, Version};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{aead::Aead, Key, KeyInit, XChaCha20Poly1305, XNonce};
+use num_bigint::BigUint;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroizing;
+
+/// Version of the keystore file format.
+const FORMAT_VERSION: u32 = 1;
+/// Extension of the profile files in the keystore directory.
+const PROFILE_EXTENSION: &str = "json";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+
+/// KeystoreError is returned when a profile cannot be stored, found or decrypted.
+#[derive(Debug)]
+pub enum KeystoreError {
+    // Io means a profile file could not be read or written
+    Io(io::Error),
+    // InvalidName means the profile name contains characters other than letters, digits, '-' and '_'
+    InvalidName(String),
+    // AlreadyExists means a profile with the name exists and would be overwritten
+    AlreadyExists(String),
+    // NotFound means there is no profile with the name
+    NotFound(String),
+    // Format means the profile file is not a keystore file this version understands
+    Format(String),
+    // WrongPassphrase means the profile could not be decrypted, because the passphrase is wrong or the file was modified
+    WrongPassphrase,
+}
+
+impl fmt::Display for KeystoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeystoreError::Io(err) => write!(f, "keystore I/O error: {}", err),
+            KeystoreError::InvalidName(name) => {
+                write!(f, "invalid profile name {:?}, use letters, digits, '-' and '_'", name)
+            }
+            KeystoreError::AlreadyExists(name) => write!(f, "profile {} already exists", name),
+            KeystoreError::NotFound(name) => write!(f, "profile {} does not exist", name),
+            KeystoreError::Format(msg) => write!(f, "invalid profile file: {}", msg),
+            KeystoreError::WrongPassphrase => write!(f, "wrong passphrase or corrupted profile"),
+        }
+    }
+}
+
+impl std::error::Error for KeystoreError {}
+
+impl From<io::Error> for KeystoreError {
+    fn from(err: io::Error) -> Self {
+        KeystoreError::Io(err)
+    }
+}
+
+/// Profile is everything a client needs to authenticate a user against a server.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Profile {
+    // secret is the secret x of the user, hex encoded in the encrypted file
+    #[serde(with = "hex_biguint")]
+    pub secret: BigUint,
+    // group_id identifies the group parameters the secret is used in, e.g. DEFAULT_GROUP_ID
+    pub group_id: String,
+    // server_url is the address of the server the user is registered with
+    pub server_url: String,
+    pub username: String,
+    // next_secret is the secret a key rotation is switching to, kept until the profile is updated after the server
+    // accepted it, so that a rotation interrupted in between can be finished
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_biguint_option")]
+    pub next_secret: Option<BigUint>,
+}
+
+/// Debug is implemented by hand so that the secret is never printed.
+impl fmt::Debug for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Profile")
+            .field("group_id", &self.group_id)
+            .field("server_url", &self.server_url)
+            .field("username", &self.username)
+            .finish_non_exhaustive()
+    }
+}
+
+/// KdfParams are the Argon2id costs used to derive the encryption key from the passphrase. They are stored in each
+/// profile file so that they can be raised without breaking existing profiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    // m_cost is the memory size in KiB
+    pub m_cost: u32,
+    // t_cost is the number of passes
+    pub t_cost: u32,
+    // p_cost is the degree of parallelism
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        KdfParams { m_cost: Params::DEFAULT_M_COST, t_cost: Params::DEFAULT_T_COST, p_cost: Params::DEFAULT_P_COST }
+    }
+}
+
+/// ProfileFile is the on-disk representation of an encrypted profile.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProfileFile {
+    version: u32,
+    kdf: KdfParams,
+    // salt, nonce and ciphertext are base64 encoded
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Keystore keeps profiles in a directory, one file per profile, each encrypted with its own passphrase using a key
+/// derived with Argon2id and XChaCha20-Poly1305.
+#[derive(Debug, Clone)]
+pub struct Keystore {
+    dir: PathBuf,
+    // kdf are the costs used for newly written profiles
+    kdf: KdfParams,
+}
+
+impl Keystore {
+    /// new opens the keystore in the given directory, which is created when the first profile is stored.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Keystore { dir: dir.into(), kdf: KdfParams::default() }
+    }
+
+    /// with_kdf_params sets the Argon2id costs used for newly written profiles.
+    pub fn with_kdf_params(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    /// dir returns the directory the profiles are stored in.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// create encrypts the profile with the passphrase and stores it under the given name.
+    /// It fails if a profile with that name already exists. The profile has reached the disk when create returns.
+    pub fn create(&self, name: &str, profile: &Profile, passphrase: &str) -> Result<(), KeystoreError> {
+        let path = self.path(name)?;
+        let contents = self.encrypt(profile, passphrase)?;
+        fs::create_dir_all(&self.dir)?;
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = match options.open(&path) {
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(KeystoreError::AlreadyExists(name.to_string()))
+            }
+            file => file?,
+        };
+        if let Err(err) = write_synced(&mut file, &contents) {
+            // A partial profile would keep the profile from being created again.
+            let _ = fs::remove_file(&path);
+            return Err(err.into());
+        }
+        sync_dir(&self.dir)?;
+        Ok(())
+    }
+
+    /// update replaces an existing profile, e.g. after the keys of the user have been rotated.
+    /// The file is replaced atomically so that a failed write leaves the old profile intact, and the new profile has
+    /// reached the disk when update returns, since it may hold the only copy of a secret the server already expects.
+    pub fn update(&self, name: &str, profile: &Profile, passphrase: &str) -> Result<(), KeystoreError> {
+        let path = self.path(name)?;
+        if !path.exists() {
+            return Err(KeystoreError::NotFound(name.to_string()));
+        }
+        let contents = self.encrypt(profile, passphrase)?;
+        let tmp = path.with_extension(format!("{}.tmp", PROFILE_EXTENSION));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        if let Err(err) = options.open(&tmp).and_then(|mut file| write_synced(&mut file, &contents)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err.into());
+        }
+        fs::rename(&tmp, &path)?;
+        sync_dir(&self.dir)?;
+        Ok(())
+    }
+
+    /// unlock decrypts the profile with the given name.
+    pub fn unlock(&self, name: &str, passphrase: &str) -> Result<Profile, KeystoreError> {
+        let path = self.path(name)?;
+        let contents = match fs::read_to_string(&path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(KeystoreError::NotFound(name.to_string())),
+            contents => contents?,
+        };
+        let file: ProfileFile = serde_json::from_str(&contents).map_err(|err| KeystoreError::Format(err.to_string()))?;
+        if file.version != FORMAT_VERSION {
+            return Err(KeystoreError::Format(format!("unsupported version {}", file.version)));
+        }
+        let salt = decode(&file.salt, "salt")?;
+        let nonce: [u8; NONCE_LEN] = decode(&file.nonce, "nonce")?
+            .try_into()
+            .map_err(|_| KeystoreError::Format("invalid nonce length".to_string()))?;
+        let ciphertext = decode(&file.ciphertext, "ciphertext")?;
+
+        let key = derive_key(passphrase, &salt, file.kdf)?;
+        let cipher = XChaCha20Poly1305::new(&Key::from(*key));
+        let plaintext = Zeroizing::new(
+            cipher
+                .decrypt(&XNonce::from(nonce), ciphertext.as_slice())
+                .map_err(|_| KeystoreError::WrongPassphrase)?,
+        );
+        serde_json::from_slice(&plaintext).map_err(|err| KeystoreError::Format(err.to_string()))
+    }
+
+    /// list returns the names of the stored profiles in alphabetical order.
+    pub fn list(&self) -> Result<Vec<String>, KeystoreError> {
+        let entries = match fs::read_dir(&self.dir) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            entries => entries?,
+        };
+        let mut names = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == PROFILE_EXTENSION) {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()).filter(|name| valid_name(name)) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    /// delete removes the profile with the given name.
+    pub fn delete(&self, name: &str) -> Result<(), KeystoreError> {
+        match fs::remove_file(self.path(name)?) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Err(KeystoreError::NotFound(name.to_string())),
+            result => Ok(result?),
+        }
+    }
+
+    /// path returns the file the profile with the given name is stored in.
+    fn path(&self, name: &str) -> Result<PathBuf, KeystoreError> {
+        if !valid_name(name) {
+            return Err(KeystoreError::InvalidName(name.to_string()));
+        }
+        Ok(self.dir.join(format!("{}.{}", name, PROFILE_EXTENSION)))
+    }
+
+    /// encrypt serializes the profile and encrypts it with a key derived from the passphrase and a fresh salt.
+    fn encrypt(&self, profile: &Profile, passphrase: &str) -> Result<String, KeystoreError> {
+        let mut rng = rand::thread_rng();
+        let mut salt = [0u8; SALT_LEN];
+        rng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LEN];
+        rng.fill_bytes(&mut nonce);
+
+        let key = derive_key(passphrase, &salt, self.kdf)?;
+        let plaintext = Zeroizing::new(serde_json::to_vec(profile).expect("profiles can always be serialized"));
+        let ciphertext = XChaCha20Poly1305::new(&Key::from(*key))
+            .encrypt(&XNonce::from(nonce), plaintext.as_slice())
+            .expect("encryption only fails for oversized messages");
+
+        let file = ProfileFile {
+            version: FORMAT_VERSION,
+            kdf: self.kdf,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        Ok(serde_json::to_string_pretty(&file).expect("profile files can always be serialized"))
+    }
+}
+
+/// valid_name returns true if the profile name can safely be used as a file name.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// derive_key derives the encryption key of a profile from its passphrase with Argon2id.
+fn derive_key(passphrase: &str, salt: &[u8], kdf: KdfParams) -> Result<Zeroizing<[u8; KEY_LEN]>, KeystoreError> {
+    let params = Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(KEY_LEN))
+        .map_err(|err| KeystoreError::Format(format!("invalid key derivation parameters: {}", err)))?;
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, key.as_mut_slice())
+        .map_err(|err| KeystoreError::Format(format!("could not derive key: {}", err)))?;
+    Ok(key)
+}
+
+/// write_synced writes the contents to the file and waits until they have reached the disk.
+fn write_synced(file: &mut fs::File, contents: &str) -> io::Result<()> {
+    io::Write::write_all(file, contents.as_bytes())?;
+    file.sync_all()
+}
+
+/// sync_dir waits until the entries of the directory, e.g. a file created or renamed in it, have reached the disk.
+/// Directories cannot be synced this way on other platforms than Unix, where it does nothing.
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    #[cfg(not(unix))]
+    let _ = dir;
+    Ok(())
+}
+
+/// decode decodes a base64 field of a profile file.
+fn decode(value: &str, field: &str) -> Result<Vec<u8>, KeystoreError> {
+    STANDARD.decode(value).map_err(|err| KeystoreError::Format(format!("invalid {}: {}", field, err)))
+}
+
+/// hex_biguint (de)serializes a BigUint as a hex string.
+mod hex_biguint {
+    use num_bigint::BigUint;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_str_radix(16))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        BigUint::parse_bytes(hex.as_bytes(), 16).ok_or_else(|| D::Error::custom("invalid hex number"))
+    }
+}
+
+/// hex_biguint_option (de)serializes an optional BigUint as a hex string.
+mod hex_biguint_option {
+    use num_bigint::BigUint;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<BigUint>, serializer: S) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => super::hex_biguint::serialize(value, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BigUint>, D::Error> {
+        #[derive(Deserialize)]
+        struct Hex(#[serde(with = "super::hex_biguint")] BigUint);
+        Ok(Option::<Hex>::deserialize(deserializer)?.map(|Hex(value)| value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{default_cfg, gen_random_number_below, DEFAULT_GROUP_ID};
+
+    /// test_keystore creates a keystore in a new temporary directory with cheap key derivation.
+    fn test_keystore() -> Keystore {
+        let dir = std::env::temp_dir().join(format!("zkp-auth-keystore-{}", rand::random::<u64>()));
+        Keystore::new(dir).with_kdf_params(KdfParams { m_cost: 64, t_cost: 1, p_cost: 1 })
+    }
+
+    fn test_profile(username: &str) -> Profile {
+        let (_, _, _, q) = default_cfg();
+        Profile {
+            secret: gen_random_number_below(&q),
+            group_id: DEFAULT_GROUP_ID.to_string(),
+            server_url: "http://127.0.0.1:50051".to_string(),
+            username: username.to_string(),
+            next_secret: None,
+        }
+    }
+
+    #[test]
+    fn test_create_and_unlock() {
+        let keystore = test_keystore();
+        let profile = test_profile("alice");
+
+        keystore.create("alice", &profile, "passphrase").unwrap();
+        assert_eq!(keystore.unlock("alice", "passphrase").unwrap(), profile);
+        assert!(matches!(keystore.unlock("alice", "wrong"), Err(KeystoreError::WrongPassphrase)));
+        assert!(matches!(keystore.unlock("bob", "passphrase"), Err(KeystoreError::NotFound(_))));
+        assert!(matches!(
+            keystore.create("alice", &test_profile("alice"), "passphrase"),
+            Err(KeystoreError::AlreadyExists(_))
+        ));
+
+        // the secret is not stored in plain text
+        let contents = fs::read_to_string(keystore.dir().join("alice.json")).unwrap();
+        assert!(!contents.contains(&profile.secret.to_str_radix(16)));
+        assert!(!contents.contains("alice"));
+        fs::remove_dir_all(keystore.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_tampered_profile() {
+        let keystore = test_keystore();
+        keystore.create("alice", &test_profile("alice"), "passphrase").unwrap();
+
+        let path = keystore.dir().join("alice.json");
+        let mut file: ProfileFile = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let mut ciphertext = STANDARD.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        file.ciphertext = STANDARD.encode(ciphertext);
+        fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+
+        assert!(matches!(keystore.unlock("alice", "passphrase"), Err(KeystoreError::WrongPassphrase)));
+        fs::remove_dir_all(keystore.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_update_list_and_delete() {
+        let keystore = test_keystore();
+        assert_eq!(keystore.list().unwrap(), Vec::<String>::new());
+
+        keystore.create("work", &test_profile("alice"), "one").unwrap();
+        keystore.create("home", &test_profile("bob"), "two").unwrap();
+        assert_eq!(keystore.list().unwrap(), vec!["home", "work"]);
+
+        let (_, _, _, q) = default_cfg();
+        let rotating = Profile { next_secret: Some(gen_random_number_below(&q)), ..test_profile("bob") };
+        keystore.update("home", &rotating, "three").unwrap();
+        assert_eq!(keystore.unlock("home", "three").unwrap(), rotating);
+        let rotated = Profile { secret: rotating.next_secret.unwrap(), next_secret: None, ..rotating };
+        keystore.update("home", &rotated, "three").unwrap();
+        assert_eq!(keystore.unlock("home", "three").unwrap(), rotated);
+        assert!(matches!(keystore.update("other", &rotated, "three"), Err(KeystoreError::NotFound(_))));
+        // no temporary file is left behind
+        assert_eq!(fs::read_dir(keystore.dir()).unwrap().count(), 2);
+
+        // a failed update leaves the profile as it was
+        let tmp = keystore.dir().join("home.json.tmp");
+        fs::create_dir(&tmp).unwrap();
+        assert!(matches!(keystore.update("home", &test_profile("bob"), "three"), Err(KeystoreError::Io(_))));
+        assert_eq!(keystore.unlock("home", "three").unwrap(), rotated);
+        fs::remove_dir(&tmp).unwrap();
+
+        keystore.delete("work").unwrap();
+        assert!(matches!(keystore.delete("work"), Err(KeystoreError::NotFound(_))));
+        assert_eq!(keystore.list().unwrap(), vec!["home"]);
+        fs::remove_dir_all(keystore.dir()).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_name() {
+        let keystore = test_keystore();
+        for name in ["", "../alice", "a/b", "alice.json"] {
+            assert!(matches!(keystore.unlock(name, "passphrase"), Err(KeystoreError::InvalidName(_))));
+        }
+    }
+}
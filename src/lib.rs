@@ -1,30 +1,118 @@
+// Without the std feature only the proofs are built, which need nothing but an allocator.
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "modp")]
 use num_bigint::{BigUint, RandBigInt};
+#[cfg(feature = "modp")]
+use rand::{CryptoRng, Rng};
+
+#[cfg(feature = "grpc-server")]
+pub mod audit;
+#[cfg(feature = "grpc-server")]
+pub mod config;
+#[cfg(feature = "ec")]
+pub mod ec;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+#[cfg(feature = "grpc-server")]
+pub mod metrics;
+/// The messages and services generated from proto/zkp_auth/auth.proto and proto/zkp_auth/admin.proto.
+#[cfg(feature = "grpc")]
+pub mod proto;
+#[cfg(feature = "modp")]
+pub mod protocol;
+#[cfg(feature = "grpc-server")]
+pub mod ratelimit;
+#[cfg(feature = "grpc-client")]
+pub mod sdk;
+#[cfg(feature = "password")]
+pub mod secret;
+#[cfg(feature = "std")]
+pub mod session;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(any(feature = "grpc-client", feature = "grpc-server"))]
+pub mod tls;
+#[cfg(feature = "grpc-server")]
+pub mod token;
 
 // P is a big prime number forming a cyclic modulus group, data taken from https://www.rfc-editor.org/rfc/rfc5114#page-15 
+#[cfg(feature = "modp")]
 const P: &[u8] = b"B10B8F96A080E01DDE92DE5EAE5D54EC52C99FBCFB06A3C69A6A9DCA52D23B616073E28675A23D189838EF1E2EE652C013ECB4AEA906112324975C3CD49B83BFACCBDD7D90C4BD7098488E9C219A73724EFFD6FAE5644738FAA31A4FF55BCCC0A151AF5F0DC8B4BD45BF37DF365C1A65E68CFDA76D4DA708DF1FB2BC2E4A4371";
 // Q us the prime order of the above group
+#[cfg(feature = "modp")]
 const Q: &[u8] = b"F518AA8781A8DF278ABA4E7D64B7CB9D49462353";
 // G is a generator of the group
+#[cfg(feature = "modp")]
 const G: &[u8] = b"A4D1CBD5C3FD34126765A442EFB99905F8104DD258AC507FD6406CFF14266D31266FEA1E5C41564B777E690F5504F213160217B4B01B886A5E91547F9E2749F4D7FBD7D3B9A92EE1909D0D2263F80A76A6A24C087A091F531DBF0A0169B6A28AD662A4D18E73AFA32D779D5918D08BC8858F4DCEF97C2A24855E6EEB22B3B2E5";
 
+// P_2048 is the 2048-bit prime of the group with 224-bit prime order subgroup, data taken from https://www.rfc-editor.org/rfc/rfc5114#page-16
+#[cfg(feature = "modp")]
+const P_2048: &[u8] = b"AD107E1E9123A9D0D660FAA79559C51FA20D64E5683B9FD1B54B1597B61D0A75E6FA141DF95A56DBAF9A3C407BA1DF15EB3D688A309C180E1DE6B85A1274A0A66D3F8152AD6AC2129037C9EDEFDA4DF8D91E8FEF55B7394B7AD5B7D0B6C12207C9F98D11ED34DBF6C6BA0B2C8BBC27BE6A00E0A0B9C49708B3BF8A317091883681286130BC8985DB1602E714415D9330278273C7DE31EFDC7310F7121FD5A07415987D9ADC0A486DCDF93ACC44328387315D75E198C641A480CD86A1B9E587E8BE60E69CC928B2B9C52172E413042E9B23F10B0E16E79763C9B53DCF4BA80A29E3FB73C16B8E75B97EF363E2FFA31F71CF9DE5384E71B81C0AC4DFFE0C10E64F";
+// Q_2048 is the prime order of the above group
+#[cfg(feature = "modp")]
+const Q_2048: &[u8] = b"801C0D34C58D93FE997177101F80535A4738CEBCBF389A99B36371EB";
+// G_2048 is a generator of the group
+#[cfg(feature = "modp")]
+const G_2048: &[u8] = b"AC4032EF4F2D9AE39DF30B5C8FFDAC506CDEBE7B89998CAF74866A08CFE4FFE3A6824A4E10B9A6F0DD921F01A70C4AFAAB739D7700C29F52C57DB17C620A8652BE5E9001A8D66AD7C17669101999024AF4D027275AC1348BB8A762D0521BC98AE247150422EA1ED409939D54DA7460CDB5F6C6B250717CBEF180EB34118E98D119529A45D6F834566E3025E316A330EFBB77A86F0C1AB15B051AE3D428C8F8ACB70A8137150B8EEB10E183EDD19963DDD9E263E4770589EF6AA21E7F5F2FF381B539CCE3409D13CD566AFBB48D6C019181E1BCFE94B30269EDFE72FE9B6AA4BD7B5A0F1C71CFFF4C19C418E1F6EC017981BC087F2A7065B384B890D3191F2BFA";
+
+/// Identifies the group returned by `default_cfg`, i.e. the 1024-bit MODP group with 160-bit prime order subgroup from RFC 5114.
+#[cfg(feature = "modp")]
+pub const DEFAULT_GROUP_ID: &str = "rfc5114-1024-160";
+
+/// Identifies the 2048-bit MODP group with 224-bit prime order subgroup from RFC 5114.
+#[cfg(feature = "modp")]
+pub const RFC5114_2048_224_GROUP_ID: &str = "rfc5114-2048-224";
+
+/// The ids of the groups `group_cfg` knows.
+#[cfg(feature = "modp")]
+pub const GROUP_IDS: &[&str] = &[DEFAULT_GROUP_ID, RFC5114_2048_224_GROUP_ID];
+
 /// Returns the default configuration for the ZKP protocol.
+#[cfg(feature = "modp")]
 pub fn default_cfg() -> (BigUint, BigUint, BigUint, BigUint) {
-    let p = BigUint::from_bytes_be(hex::decode(P).unwrap().as_slice());
-    let q = BigUint::from_bytes_be(hex::decode(Q).unwrap().as_slice());
-    let g = BigUint::from_bytes_be(hex::decode(G).unwrap().as_slice());
+    cfg_from_hex(P, Q, G)
+}
+
+/// Returns the configuration for the ZKP protocol in the group with the given id, or None if the group is unknown.
+#[cfg(feature = "modp")]
+pub fn group_cfg(group_id: &str) -> Option<(BigUint, BigUint, BigUint, BigUint)> {
+    match group_id {
+        DEFAULT_GROUP_ID => Some(default_cfg()),
+        RFC5114_2048_224_GROUP_ID => Some(cfg_from_hex(P_2048, Q_2048, G_2048)),
+        _ => None,
+    }
+}
+
+/// Returns (g, h, p, q) for the hex encoded modulus, order and generator.
+#[cfg(feature = "modp")]
+fn cfg_from_hex(p: &[u8], q: &[u8], g: &[u8]) -> (BigUint, BigUint, BigUint, BigUint) {
+    let p = BigUint::from_bytes_be(hex::decode(p).unwrap().as_slice());
+    let q = BigUint::from_bytes_be(hex::decode(q).unwrap().as_slice());
+    let g = BigUint::from_bytes_be(hex::decode(g).unwrap().as_slice());
     let exp = BigUint::from(85u32); // randomly chosen, any number would work
     let h = g.modpow(&exp, &p); // h = g^exp mod p is also a generator of the group because the group is cyclic and of prime order
 
     (g, h, p, q)
 }
 
+/// Returns a random number below the given bound drawn from the given cryptographically secure random number generator.
+#[cfg(feature = "modp")]
+pub fn random_number_below<R: Rng + CryptoRng + ?Sized>(rng: &mut R, bound: &BigUint) -> BigUint {
+    rng.gen_biguint_below(bound)
+}
+
 /// Returns a random number below the given bound.
+#[cfg(all(feature = "modp", feature = "std"))]
 pub fn gen_random_number_below(bound: &BigUint) -> BigUint {
-    let mut rng = rand::thread_rng();
-    rng.gen_biguint_below(bound)
+    random_number_below(&mut rand::thread_rng(), bound)
 }
 
 /// Zero Knowledge Proof (ZKP) struct implementing the Chaum-Pedersen protocol using a cyclic group of prime order and discrete logarithm problem.
+#[cfg(feature = "modp")]
+#[derive(Debug, Clone)]
 pub struct ZKP {
     pub g: BigUint, // generator of the group
     pub h: BigUint, // generator of the group
@@ -32,6 +120,7 @@ pub struct ZKP {
     pub q: BigUint  // prime order of the group
 }
 
+#[cfg(feature = "modp")]
 impl ZKP {
     /// solve is used by a prover to solve the discrete logarithm problem using the Chaum-Pedersen protocol.
     pub fn solve(&self, k: &BigUint, c: &BigUint, x: &BigUint) -> BigUint {   // s = k-cx mod q
@@ -47,10 +136,16 @@ impl ZKP {
         let cond2 = *r2 == (&self.h.modpow(s, &self.p) * y2.modpow(c, &self.p)).modpow(&BigUint::from(1u32), &self.p);
         cond1 && cond2
     }
-}
 
-#[cfg(test)]
+    /// is_public_key returns true if y can be a public key g^x or h^x: an element of the subgroup of order q other
+    /// than 1, which everyone knows the logarithm 0 of.
+    pub fn is_public_key(&self, y: &BigUint) -> bool {
+        let one = BigUint::from(1u32);
+        *y > one && *y < &self.p - &one && y.modpow(&self.q, &self.p) == one
+    }
+}
 
+#[cfg(all(test, feature = "modp", feature = "std"))]
 mod test {
     use super::*;
 
@@ -94,6 +189,36 @@ mod test {
         assert!(!result_wrong);
     }
 
+    #[test]
+    fn test_is_public_key() {
+        let (g, h, p, q) = default_cfg();
+        let zkp = ZKP { g: g.clone(), h: h.clone(), p: p.clone(), q };
+        let x = gen_random_number_below(&zkp.q);
+        assert!(zkp.is_public_key(&g.modpow(&x, &p)));
+        assert!(zkp.is_public_key(&h.modpow(&x, &p)));
+        for y in [BigUint::ZERO, BigUint::from(1u32), &p - 1u32, p.clone(), &p + &g, BigUint::from(2u32)] {
+            assert!(!zkp.is_public_key(&y), "{}", y);
+        }
+    }
+
+    #[test]
+    fn test_group_cfg() {
+        assert!(group_cfg("rfc3526-2048").is_none());
+        for group_id in GROUP_IDS {
+            let (g, h, p, q) = group_cfg(group_id).unwrap();
+            // both generators have the order q
+            assert_eq!(g.modpow(&q, &p), BigUint::from(1u32));
+            assert_eq!(h.modpow(&q, &p), BigUint::from(1u32));
+            let zkp = ZKP { g: g.clone(), h: h.clone(), p: p.clone(), q: q.clone() };
+
+            let x = gen_random_number_below(&q);
+            let k = gen_random_number_below(&q);
+            let c = gen_random_number_below(&q);
+            let s = zkp.solve(&k, &c, &x);
+            assert!(zkp.verify(&g.modpow(&k, &p), &h.modpow(&k, &p), &g.modpow(&x, &p), &h.modpow(&x, &p), &c, &s));
+        }
+    }
+
     #[test]
     fn test_2048_bits_prime() {
         // the group was copied from https://www.rfc-editor.org/rfc/rfc5114#page-16
@@ -0,0 +1,214 @@
+use prometheus_client::{
+    encoding::{text::encode, EncodeLabelSet, EncodeLabelValue, LabelValueEncoder},
+    metrics::{
+        counter::Counter,
+        family::Family,
+        histogram::{exponential_buckets, Histogram},
+    },
+    registry::Registry,
+};
+use std::{
+    fmt::{self, Write},
+    sync::Arc,
+    time::Instant,
+};
+
+/// The content type of the OpenMetrics text format returned by `Metrics::encode`, which ends with `# EOF` and names
+/// counters without their `_total` suffix in its `# TYPE` lines, so scrapers must not parse it as the Prometheus one.
+pub const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Outcome is why a proof was accepted or rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    // Success means the proof was correct
+    Success,
+    // WrongProof means the answer did not solve the challenge
+    WrongProof,
+    // Expired means the challenge was answered after its time-to-live
+    Expired,
+    // UnknownChallenge means the challenge was never issued or has been answered or reaped already
+    UnknownChallenge,
+    // UnknownUser means the user the challenge was issued for no longer exists
+    UnknownUser,
+    // Disabled means the user the challenge was issued for has been disabled since
+    Disabled,
+    // RateLimited means the user was backing off or locked out after failed proofs when the challenge was answered
+    RateLimited,
+}
+
+impl Outcome {
+    /// as_str returns the label value of the outcome.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::WrongProof => "wrong_proof",
+            Outcome::Expired => "expired",
+            Outcome::UnknownChallenge => "unknown_challenge",
+            Outcome::UnknownUser => "unknown_user",
+            Outcome::Disabled => "disabled",
+            Outcome::RateLimited => "rate_limited",
+        }
+    }
+}
+
+impl EncodeLabelValue for Outcome {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), fmt::Error> {
+        encoder.write_str(self.as_str())
+    }
+}
+
+/// Limit is the rate limit a request was rejected by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Limit {
+    // Peer is the limit of requests per peer address
+    Peer,
+    // User is the limit of challenges per user
+    User,
+    // Backoff means the user has to wait after a failed proof
+    Backoff,
+    // Lockout means the user failed too many proofs in a row
+    Lockout,
+}
+
+impl Limit {
+    /// as_str returns the label value of the limit.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Limit::Peer => "peer",
+            Limit::User => "user",
+            Limit::Backoff => "backoff",
+            Limit::Lockout => "lockout",
+        }
+    }
+}
+
+impl EncodeLabelValue for Limit {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), fmt::Error> {
+        encoder.write_str(self.as_str())
+    }
+}
+
+/// VerificationLabels distinguishes the verifications by their outcome.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct VerificationLabels {
+    pub outcome: Outcome,
+}
+
+/// RateLimitLabels distinguishes the rate limited requests by the limit they hit.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+pub struct RateLimitLabels {
+    pub limit: Limit,
+}
+
+/// Metrics holds the counters and histograms of the authentication flows. Clones share the same metrics, so the
+/// service and the rate limiter can each hold one.
+#[derive(Debug, Clone)]
+pub struct Metrics {
+    registry: Arc<Registry>,
+    // registrations counts the users registered
+    pub registrations: Counter,
+    // challenges_issued counts the challenges handed out
+    pub challenges_issued: Counter,
+    // challenges_expired counts the challenges which were answered too late or removed unanswered by the reaper
+    pub challenges_expired: Counter,
+    // verifications counts the answered challenges by outcome
+    pub verifications: Family<VerificationLabels, Counter>,
+    // rate_limited counts the rejected requests by the limit they hit
+    pub rate_limited: Family<RateLimitLabels, Counter>,
+    // verification_duration is how long checking an answer takes, including the look-ups
+    pub verification_duration: Histogram,
+    // modpow_duration is how long the modular exponentiations of a verification take
+    pub modpow_duration: Histogram,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Metrics {
+    /// new creates the metrics and registers them with the prefix zkpauth.
+    pub fn new() -> Self {
+        let mut registry = Registry::with_prefix("zkpauth");
+        let registrations = Counter::default();
+        registry.register("registrations", "Users registered", registrations.clone());
+        let challenges_issued = Counter::default();
+        registry.register("challenges_issued", "Challenges issued", challenges_issued.clone());
+        let challenges_expired = Counter::default();
+        registry.register("challenges_expired", "Challenges which expired before they were answered", challenges_expired.clone());
+        let verifications = Family::<VerificationLabels, Counter>::default();
+        registry.register("verifications", "Answered challenges by outcome", verifications.clone());
+        let rate_limited = Family::<RateLimitLabels, Counter>::default();
+        registry.register("rate_limited", "Requests rejected by a rate limit", rate_limited.clone());
+        // 100µs to about 3s
+        let verification_duration = Histogram::new(exponential_buckets(0.0001, 2.0, 16));
+        registry.register("verification_duration_seconds", "Time taken to check an answer", verification_duration.clone());
+        let modpow_duration = Histogram::new(exponential_buckets(0.0001, 2.0, 16));
+        registry.register("modpow_duration_seconds", "Time taken by the modular exponentiations of a verification", modpow_duration.clone());
+
+        Metrics {
+            registry: Arc::new(registry),
+            registrations,
+            challenges_issued,
+            challenges_expired,
+            verifications,
+            rate_limited,
+            verification_duration,
+            modpow_duration,
+        }
+    }
+
+    /// verification counts an answered challenge with the given outcome.
+    pub fn verification(&self, outcome: Outcome) {
+        self.verifications.get_or_create(&VerificationLabels { outcome }).inc();
+        if outcome == Outcome::Expired {
+            self.challenges_expired.inc();
+        }
+    }
+
+    /// rate_limited counts a request rejected by the given limit.
+    pub fn rate_limited(&self, limit: Limit) {
+        self.rate_limited.get_or_create(&RateLimitLabels { limit }).inc();
+    }
+
+    /// encode returns the metrics in the OpenMetrics text format, see CONTENT_TYPE.
+    pub fn encode(&self) -> String {
+        let mut output = String::new();
+        encode(&mut output, &self.registry).expect("writing to a string does not fail");
+        output
+    }
+}
+
+/// observe_since records the seconds elapsed since start in the histogram.
+pub fn observe_since(histogram: &Histogram, start: Instant) {
+    histogram.observe(start.elapsed().as_secs_f64());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let metrics = Metrics::new();
+        metrics.registrations.inc();
+        metrics.verification(Outcome::Success);
+        metrics.verification(Outcome::Success);
+        metrics.verification(Outcome::Expired);
+        metrics.rate_limited(Limit::Lockout);
+        metrics.clone().modpow_duration.observe(0.002);
+
+        let output = metrics.encode();
+        assert!(output.contains("zkpauth_registrations_total 1\n"));
+        assert!(output.contains("zkpauth_challenges_issued_total 0\n"));
+        assert!(output.contains("zkpauth_challenges_expired_total 1\n"));
+        assert!(output.contains("zkpauth_verifications_total{outcome=\"success\"} 2\n"));
+        assert!(output.contains("zkpauth_verifications_total{outcome=\"expired\"} 1\n"));
+        assert!(output.contains("zkpauth_rate_limited_total{limit=\"lockout\"} 1\n"));
+        // clones share the metrics
+        assert!(output.contains("zkpauth_modpow_duration_seconds_count 1\n"));
+        assert!(output.contains("zkpauth_verification_duration_seconds_count 0\n"));
+        assert!(output.ends_with("# EOF\n"));
+    }
+}
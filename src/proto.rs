@@ -0,0 +1,144 @@
+use crate::protocol::{Challenge, Commitment, Proof, PublicKeys};
+use num_bigint::BigUint;
+
+tonic::include_proto!("zkp_auth");
+
+/// Name of the metadata entry telling a rate limited client after how many seconds to retry.
+pub const RETRY_AFTER: &str = "retry-after";
+
+/// Name of the metadata entry, or HTTP header, naming the realm a call is made in. Calls without it are made in the
+/// default realm.
+pub const REALM: &str = "zkpauth-realm";
+
+/// FILE_DESCRIPTOR_SET describes auth.proto and admin.proto to clients of the reflection service, such as grpcurl.
+#[cfg(feature = "grpc-server")]
+pub const FILE_DESCRIPTOR_SET: &[u8] = tonic::include_file_descriptor_set!("zkp_auth_descriptor");
+
+/// base64_bytes (de)serializes the bytes fields of the messages as base64 strings in JSON.
+#[cfg(feature = "grpc-server")]
+pub(crate) mod base64_bytes {
+    use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&BASE64.encode(bytes))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        BASE64.decode(encoded).map_err(|_| D::Error::custom("invalid base64"))
+    }
+}
+
+impl RegisterRequest {
+    /// new creates the request registering the user with the given public keys.
+    pub fn new(user: impl Into<String>, keys: &PublicKeys) -> Self {
+        RegisterRequest { user: user.into(), y1: keys.y1.to_bytes_be(), y2: keys.y2.to_bytes_be() }
+    }
+
+    /// public_keys returns the public keys the user registers with.
+    pub fn public_keys(&self) -> PublicKeys {
+        PublicKeys { y1: BigUint::from_bytes_be(&self.y1), y2: BigUint::from_bytes_be(&self.y2) }
+    }
+}
+
+impl AuthenticationChallengeRequest {
+    /// new creates the request asking for a challenge to the commitment of the user.
+    pub fn new(user: impl Into<String>, commitment: &Commitment) -> Self {
+        AuthenticationChallengeRequest {
+            user: user.into(),
+            r1: commitment.r1.to_bytes_be(),
+            r2: commitment.r2.to_bytes_be(),
+        }
+    }
+
+    /// commitment returns the commitment of the user.
+    pub fn commitment(&self) -> Commitment {
+        Commitment { r1: BigUint::from_bytes_be(&self.r1), r2: BigUint::from_bytes_be(&self.r2) }
+    }
+}
+
+impl From<Challenge> for AuthenticationChallengeResponse {
+    fn from(challenge: Challenge) -> Self {
+        AuthenticationChallengeResponse { auth_id: challenge.auth_id, c: challenge.c.to_bytes_be() }
+    }
+}
+
+impl From<AuthenticationChallengeResponse> for Challenge {
+    fn from(response: AuthenticationChallengeResponse) -> Self {
+        Challenge { auth_id: response.auth_id, c: BigUint::from_bytes_be(&response.c) }
+    }
+}
+
+impl From<Proof> for AuthenticationAnswerRequest {
+    fn from(proof: Proof) -> Self {
+        AuthenticationAnswerRequest { auth_id: proof.auth_id, s: proof.s.to_bytes_be() }
+    }
+}
+
+impl From<AuthenticationAnswerRequest> for Proof {
+    fn from(request: AuthenticationAnswerRequest) -> Self {
+        Proof { auth_id: request.auth_id, s: BigUint::from_bytes_be(&request.s) }
+    }
+}
+
+impl RotateKeysRequest {
+    /// with_proof creates the request replacing the public keys of the user who answered a challenge.
+    pub fn with_proof(proof: Proof, new_keys: &PublicKeys) -> Self {
+        RotateKeysRequest {
+            auth_id: proof.auth_id,
+            s: proof.s.to_bytes_be(),
+            y1: new_keys.y1.to_bytes_be(),
+            y2: new_keys.y2.to_bytes_be(),
+            session_id: String::new(),
+        }
+    }
+
+    /// with_session creates the request replacing the public keys of the user the session belongs to.
+    pub fn with_session(session_id: impl Into<String>, new_keys: &PublicKeys) -> Self {
+        RotateKeysRequest {
+            auth_id: String::new(),
+            s: Vec::new(),
+            y1: new_keys.y1.to_bytes_be(),
+            y2: new_keys.y2.to_bytes_be(),
+            session_id: session_id.into(),
+        }
+    }
+
+    /// proof returns the answer to the challenge, which is only used if the request carries no session.
+    pub fn proof(&self) -> Proof {
+        Proof { auth_id: self.auth_id.clone(), s: BigUint::from_bytes_be(&self.s) }
+    }
+
+    /// public_keys returns the new public keys of the user.
+    pub fn public_keys(&self) -> PublicKeys {
+        PublicKeys { y1: BigUint::from_bytes_be(&self.y1), y2: BigUint::from_bytes_be(&self.y2) }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let keys = PublicKeys { y1: BigUint::from(123u32), y2: BigUint::from(456u32) };
+        let request = RegisterRequest::new("alice", &keys);
+        assert_eq!(request.user, "alice");
+        assert_eq!(request.public_keys(), keys);
+
+        let commitment = Commitment { r1: BigUint::from(789u32), r2: BigUint::from(101112u32) };
+        assert_eq!(AuthenticationChallengeRequest::new("alice", &commitment).commitment(), commitment);
+
+        let challenge = Challenge { auth_id: "auth".to_string(), c: BigUint::from(42u32) };
+        assert_eq!(Challenge::from(AuthenticationChallengeResponse::from(challenge.clone())), challenge);
+
+        let proof = Proof { auth_id: "auth".to_string(), s: BigUint::from(7u32) };
+        assert_eq!(Proof::from(AuthenticationAnswerRequest::from(proof.clone())), proof);
+
+        let request = RotateKeysRequest::with_proof(proof.clone(), &keys);
+        assert_eq!((request.proof(), request.public_keys()), (proof, keys.clone()));
+        assert!(request.session_id.is_empty());
+        assert_eq!(RotateKeysRequest::with_session("session", &keys).session_id, "session");
+    }
+}
@@ -0,0 +1,107 @@
+use crate::{random_number_below, ZKP};
+use alloc::string::String;
+use num_bigint::BigUint;
+use rand::{CryptoRng, Rng};
+
+/// PublicKeys are the values y1 = g^x mod p and y2 = h^x mod p a user registers with, where x is the user's secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKeys {
+    pub y1: BigUint,
+    pub y2: BigUint,
+}
+
+impl PublicKeys {
+    /// from_secret computes the public keys of the secret x.
+    pub fn from_secret(zkp: &ZKP, x: &BigUint) -> Self {
+        PublicKeys { y1: zkp.g.modpow(x, &zkp.p), y2: zkp.h.modpow(x, &zkp.p) }
+    }
+}
+
+/// Commitment holds the values r1 = g^k mod p and r2 = h^k mod p the prover sends before it is challenged, where k
+/// is a random number only the prover knows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Commitment {
+    pub r1: BigUint,
+    pub r2: BigUint,
+}
+
+impl Commitment {
+    /// from_nonce computes the commitment to the random number k.
+    pub fn from_nonce(zkp: &ZKP, k: &BigUint) -> Self {
+        Commitment { r1: zkp.g.modpow(k, &zkp.p), r2: zkp.h.modpow(k, &zkp.p) }
+    }
+}
+
+/// Challenge is the random number c the verifier answers a commitment with, identified by auth_id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub auth_id: String,
+    pub c: BigUint,
+}
+
+/// Proof is the prover's answer s = k - c * x mod q to the challenge identified by auth_id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Proof {
+    pub auth_id: String,
+    pub s: BigUint,
+}
+
+impl ZKP {
+    /// commit is used by a prover to pick the random number k below q and compute its commitment.
+    /// k must be kept secret and only be used to answer a single challenge.
+    pub fn commit<R: Rng + CryptoRng + ?Sized>(&self, rng: &mut R) -> (BigUint, Commitment) {
+        let k = random_number_below(rng, &self.q);
+        let commitment = Commitment::from_nonce(self, &k);
+        (k, commitment)
+    }
+
+    /// challenge is used by a verifier to pick the random challenge c below q.
+    pub fn challenge<R: Rng + CryptoRng + ?Sized>(&self, rng: &mut R) -> BigUint {
+        random_number_below(rng, &self.q)
+    }
+
+    /// verify_proof checks the answer s to the challenge c for the given public keys and commitment.
+    pub fn verify_proof(&self, keys: &PublicKeys, commitment: &Commitment, c: &BigUint, s: &BigUint) -> bool {
+        self.verify(&commitment.r1, &commitment.r2, &keys.y1, &keys.y2, c, s)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test {
+    use super::*;
+    use crate::{default_cfg, gen_random_number_below};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_verify_proof() {
+        let (g, h, p, q) = default_cfg();
+        let zkp = ZKP { g, h, p, q };
+        let x = gen_random_number_below(&zkp.q);
+        let k = gen_random_number_below(&zkp.q);
+        let c = gen_random_number_below(&zkp.q);
+
+        let keys = PublicKeys::from_secret(&zkp, &x);
+        let commitment = Commitment::from_nonce(&zkp, &k);
+        let s = zkp.solve(&k, &c, &x);
+        assert!(zkp.verify_proof(&keys, &commitment, &c, &s));
+        assert!(!zkp.verify_proof(&keys, &commitment, &c, &(s + 1u32)));
+    }
+
+    #[test]
+    fn test_explicit_rng() {
+        let (g, h, p, q) = default_cfg();
+        let zkp = ZKP { g, h, p, q };
+        let x = gen_random_number_below(&zkp.q);
+        let keys = PublicKeys::from_secret(&zkp, &x);
+
+        let mut rng = StdRng::seed_from_u64(1);
+        let (k, commitment) = zkp.commit(&mut rng);
+        let c = zkp.challenge(&mut rng);
+        assert!(k < zkp.q && c < zkp.q);
+        assert!(zkp.verify_proof(&keys, &commitment, &c, &zkp.solve(&k, &c, &x)));
+
+        // the same seed picks the same numbers
+        let mut rng = StdRng::seed_from_u64(1);
+        assert_eq!(zkp.commit(&mut rng), (k, commitment));
+    }
+}
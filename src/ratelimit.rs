@@ -0,0 +1,358 @@
+// The checks return tonic::Status directly so that handlers can pass it on with `?`, which clippy considers large.
+#![allow(clippy::result_large_err)]
+
+use dashmap::DashMap;
+use std::{
+    future::Future,
+    hash::Hash,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+use tonic::{
+    metadata::MetadataValue,
+    transport::server::{TcpConnectInfo, TlsConnectInfo},
+    Code, Status,
+};
+use tower::{Layer, Service};
+
+use crate::metrics::{Limit, Metrics};
+pub use crate::proto::RETRY_AFTER;
+
+/// RateLimitConfig describes how many requests are allowed and how failed proofs are punished.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitConfig {
+    // peer_burst is how many requests a peer address can make at once
+    pub peer_burst: u32,
+    // peer_per_second is how many requests a peer address can make per second in the long run
+    pub peer_per_second: f64,
+    // user_burst is how many challenges can be requested for a username at once
+    pub user_burst: u32,
+    // user_per_second is how many challenges can be requested for a username per second in the long run
+    pub user_per_second: f64,
+    // backoff_base is how long a user has to wait after the first failed proof; it doubles with each further failure
+    pub backoff_base: Duration,
+    // backoff_max caps the wait after a failed proof
+    pub backoff_max: Duration,
+    // lockout_threshold is the number of consecutive failed proofs after which the user is locked out
+    pub lockout_threshold: u32,
+    // lockout_duration is how long a locked out user has to wait
+    pub lockout_duration: Duration,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            peer_burst: 20,
+            peer_per_second: 10.0,
+            user_burst: 10,
+            user_per_second: 1.0,
+            backoff_base: Duration::from_secs(1),
+            backoff_max: Duration::from_secs(60),
+            lockout_threshold: 10,
+            lockout_duration: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+/// TokenBucket allows bursts of up to capacity requests, refilled at a constant rate.
+#[derive(Debug)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// take removes a token from the bucket or returns how long it takes until the next token is available.
+    fn take(&mut self, now: Instant, capacity: u32, per_second: f64) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(capacity as f64);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else if per_second > 0.0 {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        } else {
+            Err(Duration::MAX)
+        }
+    }
+
+    /// is_full returns true if the bucket would be full at the given moment, i.e. it carries no state worth keeping.
+    fn is_full(&self, now: Instant, capacity: u32, per_second: f64) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens + elapsed * per_second >= capacity as f64
+    }
+}
+
+/// Failures tracks the consecutive failed proofs of a user.
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    blocked_until: Instant,
+}
+
+/// RateLimiter keeps the token buckets of peers and users and the failed proofs of users, in sharded maps so that
+/// requests from different peers and for different users rarely wait for each other.
+#[derive(Debug)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    peers: DashMap<IpAddr, TokenBucket>,
+    users: DashMap<String, TokenBucket>,
+    failures: DashMap<String, Failures>,
+    // metrics counts the rejected requests
+    metrics: Metrics,
+}
+
+impl RateLimiter {
+    /// new creates a rate limiter enforcing the given configuration.
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            peers: DashMap::new(),
+            users: DashMap::new(),
+            failures: DashMap::new(),
+            metrics: Metrics::new(),
+        }
+    }
+
+    /// with_metrics makes the rate limiter count the requests it rejects in the given metrics.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// check_peer takes a token from the bucket of the given peer address.
+    pub fn check_peer(&self, peer: IpAddr) -> Result<(), Status> {
+        let RateLimitConfig { peer_burst, peer_per_second, .. } = self.config;
+        take(&self.peers, peer, peer_burst, peer_per_second).map_err(|retry_after| {
+            self.metrics.rate_limited(Limit::Peer);
+            resource_exhausted("too many requests from this address", retry_after)
+        })
+    }
+
+    /// check_user takes a token from the bucket of the given user and fails if the user is backing off or locked out.
+    pub fn check_user(&self, user: &str) -> Result<(), Status> {
+        self.check_blocked(user)?;
+
+        let RateLimitConfig { user_burst, user_per_second, .. } = self.config;
+        take(&self.users, user.to_string(), user_burst, user_per_second).map_err(|retry_after| {
+            self.metrics.rate_limited(Limit::User);
+            resource_exhausted(&format!("too many attempts for user: {}", user), retry_after)
+        })
+    }
+
+    /// check_blocked fails if the given user is backing off or locked out after failed proofs. Answers to challenges
+    /// fetched before are checked too, so that they cannot be used to guess on during a lockout.
+    pub fn check_blocked(&self, user: &str) -> Result<(), Status> {
+        let now = Instant::now();
+        if let Some(failures) = self.failures.get(user) {
+            if failures.blocked_until > now {
+                let (limit, reason) = if failures.count >= self.config.lockout_threshold {
+                    (Limit::Lockout, format!("User: {} is temporarily locked out", user))
+                } else {
+                    (Limit::Backoff, format!("User: {} has to wait before trying again", user))
+                };
+                self.metrics.rate_limited(limit);
+                return Err(resource_exhausted(&reason, failures.blocked_until - now));
+            }
+        }
+        Ok(())
+    }
+
+    /// record_failure registers a failed proof of the given user, making the user back off or locking them out.
+    pub fn record_failure(&self, user: &str) {
+        let now = Instant::now();
+        let mut entry = self.failures.entry(user.to_string()).or_insert(Failures { count: 0, blocked_until: now });
+        entry.count += 1;
+        let wait = if entry.count >= self.config.lockout_threshold {
+            self.config.lockout_duration
+        } else {
+            let factor = 2u32.saturating_pow(entry.count - 1);
+            self.config.backoff_base.saturating_mul(factor).min(self.config.backoff_max)
+        };
+        entry.blocked_until = now + wait;
+    }
+
+    /// record_success forgets the failed proofs of the given user.
+    pub fn record_success(&self, user: &str) {
+        self.failures.remove(user);
+    }
+
+    /// prune forgets the buckets which have refilled and the failures which no longer block anyone
+    /// and have been forgiven, i.e. happened more than a lockout duration ago.
+    pub fn prune(&self) {
+        let now = Instant::now();
+        let RateLimitConfig { peer_burst, peer_per_second, user_burst, user_per_second, lockout_duration, .. } = self.config;
+        self.peers.retain(|_, bucket| !bucket.is_full(now, peer_burst, peer_per_second));
+        self.users.retain(|_, bucket| !bucket.is_full(now, user_burst, user_per_second));
+        self.failures.retain(|_, failures| failures.blocked_until + lockout_duration > now);
+    }
+}
+
+/// take removes a token from the bucket with the given key, creating a full bucket for new keys.
+fn take<K: Eq + Hash>(buckets: &DashMap<K, TokenBucket>, key: K, capacity: u32, per_second: f64) -> Result<(), Duration> {
+    let now = Instant::now();
+    buckets
+        .entry(key)
+        .or_insert(TokenBucket { tokens: capacity as f64, updated: now })
+        .take(now, capacity, per_second)
+}
+
+/// resource_exhausted creates the status returned to rate limited clients, telling them when to retry.
+pub fn resource_exhausted(message: &str, retry_after: Duration) -> Status {
+    let mut status = Status::new(Code::ResourceExhausted, message);
+    // Round up so that clients retrying after the given number of seconds are not rejected again.
+    let seconds = retry_after.as_secs().saturating_add(u64::from(retry_after.subsec_nanos() > 0));
+    status.metadata_mut().insert(RETRY_AFTER, MetadataValue::from(seconds));
+    status
+}
+
+/// RateLimitLayer limits the requests every peer address can make to the services it wraps.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitLayer {
+    /// new creates a layer which takes a token from the peer's bucket of the given rate limiter for every request.
+    pub fn new(limiter: Arc<RateLimiter>) -> Self {
+        RateLimitLayer { limiter }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService { inner, limiter: self.limiter.clone() }
+    }
+}
+
+/// RateLimitService rejects requests with ResourceExhausted once their peer address ran out of tokens.
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    limiter: Arc<RateLimiter>,
+}
+
+impl<S, B> Service<http::Request<B>> for RateLimitService<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<tonic::body::BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let extensions = request.extensions();
+        let peer = extensions
+            .get::<TcpConnectInfo>()
+            .or_else(|| extensions.get::<TlsConnectInfo<TcpConnectInfo>>().map(|info| info.get_ref()))
+            .and_then(|info| info.remote_addr())
+            .map(|addr| addr.ip());
+        if let Some(peer) = peer {
+            if let Err(status) = self.limiter.check_peer(peer) {
+                return Box::pin(async move { Ok(status.into_http()) });
+            }
+        }
+        Box::pin(self.inner.call(request))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            peer_burst: 2,
+            peer_per_second: 1.0,
+            user_burst: 3,
+            user_per_second: 0.0,
+            backoff_base: Duration::from_secs(2),
+            backoff_max: Duration::from_secs(5),
+            lockout_threshold: 4,
+            lockout_duration: Duration::from_secs(600),
+        }
+    }
+
+    fn retry_after(status: &Status) -> u64 {
+        status.metadata().get(RETRY_AFTER).unwrap().to_str().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn test_peer_bucket() {
+        let limiter = RateLimiter::new(config());
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+
+        assert!(limiter.check_peer(peer).is_ok());
+        assert!(limiter.check_peer(peer).is_ok());
+        let status = limiter.check_peer(peer).unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(retry_after(&status), 1);
+
+        // other peers have their own bucket
+        assert!(limiter.check_peer("10.0.0.2".parse().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_user_bucket() {
+        let limiter = RateLimiter::new(config());
+        for _ in 0..3 {
+            assert!(limiter.check_user("alice").is_ok());
+        }
+        assert_eq!(limiter.check_user("alice").unwrap_err().code(), Code::ResourceExhausted);
+        assert!(limiter.check_user("bob").is_ok());
+    }
+
+    #[test]
+    fn test_backoff_and_lockout() {
+        let metrics = Metrics::new();
+        let limiter = RateLimiter::new(RateLimitConfig { user_per_second: 1.0, ..config() }).with_metrics(metrics.clone());
+
+        // the wait doubles with every failure up to the maximum
+        limiter.record_failure("alice");
+        assert_eq!(retry_after(&limiter.check_user("alice").unwrap_err()), 2);
+        limiter.record_failure("alice");
+        assert_eq!(retry_after(&limiter.check_user("alice").unwrap_err()), 4);
+        limiter.record_failure("alice");
+        assert_eq!(retry_after(&limiter.check_user("alice").unwrap_err()), 5);
+
+        // the fourth failure locks the user out
+        limiter.record_failure("alice");
+        let status = limiter.check_user("alice").unwrap_err();
+        assert!(status.message().contains("locked out"));
+        assert_eq!(retry_after(&status), 600);
+        let rejected = |limit| metrics.rate_limited.get_or_create(&crate::metrics::RateLimitLabels { limit }).get();
+        assert_eq!((rejected(Limit::Backoff), rejected(Limit::Lockout), rejected(Limit::User)), (3, 1, 0));
+
+        // a success resets the failures
+        limiter.record_success("alice");
+        assert!(limiter.check_user("alice").is_ok());
+    }
+
+    #[test]
+    fn test_prune() {
+        let limiter = RateLimiter::new(RateLimitConfig { peer_per_second: 1000.0, ..config() });
+        let peer: IpAddr = "10.0.0.1".parse().unwrap();
+        limiter.check_peer(peer).unwrap();
+        limiter.check_user("alice").unwrap();
+        limiter.record_failure("alice");
+
+        std::thread::sleep(Duration::from_millis(10));
+        limiter.prune();
+
+        // the refilled peer bucket is gone while the user bucket, which never refills, and the failure stay
+        assert!(limiter.peers.is_empty());
+        assert_eq!(limiter.users.len(), 1);
+        assert_eq!(limiter.failures.len(), 1);
+    }
+}
@@ -0,0 +1,399 @@
+use crate::{
+    proto::{
+        auth_client::AuthClient, AuthenticationAnswerRequest, AuthenticationChallengeRequest, LogoutRequest,
+        RefreshSessionRequest, RegisterRequest, RotateKeysRequest, ValidateSessionRequest, REALM, RETRY_AFTER,
+    },
+    protocol::{Challenge, Proof, PublicKeys},
+    session::{ClientMetadata, Session as ServerSession},
+    ZKP,
+};
+use num_bigint::BigUint;
+use std::{
+    fmt,
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tonic::{
+    metadata::{errors::InvalidMetadataValue, AsciiMetadataValue, MetadataValue},
+    transport::{Channel, Endpoint},
+    Code, Request, Status,
+};
+
+/// Client is the generated client the SDK calls the server with. With the otel feature it sends the trace context
+/// of the current span along with every request.
+#[cfg(feature = "otel")]
+type Client = AuthClient<tonic::service::interceptor::InterceptedService<Channel, crate::telemetry::TraceContextInterceptor>>;
+#[cfg(not(feature = "otel"))]
+type Client = AuthClient<Channel>;
+
+/// SdkError is returned by the AuthClientSdk. Server errors the SDK knows about get their own variant.
+#[derive(Debug)]
+pub enum SdkError {
+    // Connect means the server could not be reached
+    Connect(tonic::transport::Error),
+    // AlreadyRegistered means the username is taken
+    AlreadyRegistered,
+    // UnknownUser means the user is not registered
+    UnknownUser,
+    // ProofRejected means the server did not accept the proof, i.e. the secret is wrong
+    ProofRejected,
+    // ChallengeExpired means the challenge was not answered in time or was already used
+    ChallengeExpired,
+    // RateLimited means the server asks the client to slow down, for how long if it said so
+    RateLimited { retry_after: Option<Duration> },
+    // InvalidSession means the session does not exist or has expired
+    InvalidSession,
+    // Rpc is any other error returned by the server
+    Rpc(Status),
+}
+
+impl SdkError {
+    /// code returns the gRPC status code the server answered with, if the error came from the server.
+    pub fn code(&self) -> Option<Code> {
+        match self {
+            SdkError::Connect(_) => None,
+            SdkError::AlreadyRegistered => Some(Code::AlreadyExists),
+            SdkError::UnknownUser => Some(Code::NotFound),
+            SdkError::ProofRejected => Some(Code::PermissionDenied),
+            SdkError::ChallengeExpired => Some(Code::DeadlineExceeded),
+            SdkError::RateLimited { .. } => Some(Code::ResourceExhausted),
+            SdkError::InvalidSession => Some(Code::Unauthenticated),
+            SdkError::Rpc(status) => Some(status.code()),
+        }
+    }
+
+    /// is_transient returns true for errors which may go away when the request is repeated.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, SdkError::Connect(_) | SdkError::ChallengeExpired)
+            || matches!(self, SdkError::Rpc(status) if status.code() == Code::Unavailable)
+    }
+}
+
+impl fmt::Display for SdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SdkError::Connect(err) => write!(f, "could not connect to the server: {}", err),
+            SdkError::AlreadyRegistered => write!(f, "the user is already registered"),
+            SdkError::UnknownUser => write!(f, "the user is not registered"),
+            SdkError::ProofRejected => write!(f, "the server rejected the proof"),
+            SdkError::ChallengeExpired => write!(f, "the challenge expired"),
+            SdkError::RateLimited { retry_after: Some(wait) } => {
+                write!(f, "rate limited, retry after {} seconds", wait.as_secs())
+            }
+            SdkError::RateLimited { retry_after: None } => write!(f, "rate limited"),
+            SdkError::InvalidSession => write!(f, "invalid or expired session"),
+            SdkError::Rpc(status) => write!(f, "{} ({:?})", status.message(), status.code()),
+        }
+    }
+}
+
+impl std::error::Error for SdkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SdkError::Connect(err) => Some(err),
+            SdkError::Rpc(status) => Some(status),
+            _ => None,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for SdkError {
+    fn from(err: tonic::transport::Error) -> Self {
+        SdkError::Connect(err)
+    }
+}
+
+impl From<Status> for SdkError {
+    fn from(status: Status) -> Self {
+        match status.code() {
+            Code::AlreadyExists => SdkError::AlreadyRegistered,
+            Code::NotFound => SdkError::UnknownUser,
+            Code::PermissionDenied => SdkError::ProofRejected,
+            Code::DeadlineExceeded => SdkError::ChallengeExpired,
+            Code::Unauthenticated => SdkError::InvalidSession,
+            Code::ResourceExhausted => {
+                let retry_after = status
+                    .metadata()
+                    .get(RETRY_AFTER)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok())
+                    .map(Duration::from_secs);
+                SdkError::RateLimited { retry_after }
+            }
+            _ => SdkError::Rpc(status),
+        }
+    }
+}
+
+/// answer_error converts the status returned for an answered challenge. The server reports challenges it does not
+/// know as not found, which for a challenge the client just received means it expired and was removed.
+fn answer_error(status: Status) -> SdkError {
+    match status.code() {
+        Code::NotFound => SdkError::ChallengeExpired,
+        _ => status.into(),
+    }
+}
+
+/// RetryPolicy controls how often an operation failing with a transient error is attempted, waiting with exponential
+/// backoff between the attempts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // max_attempts is how often an operation is attempted in total; 1 disables retries
+    pub max_attempts: u32,
+    // initial_backoff is the wait before the second attempt; it doubles with every further attempt
+    pub initial_backoff: Duration,
+    // max_backoff caps the wait between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 3, initial_backoff: Duration::from_millis(100), max_backoff: Duration::from_secs(2) }
+    }
+}
+
+impl RetryPolicy {
+    /// backoff returns how long to wait after the given failed attempt, counting from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Session is a session started by login.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    // id identifies the session in later calls; it is a signed token if the server issues tokens
+    pub id: String,
+    pub user: String,
+    pub expires_at: SystemTime,
+}
+
+/// AuthClientSdk authenticates users against the ZKP auth server. It runs the commitment, challenge and response
+/// steps of the Chaum-Pedersen protocol and retries operations failing with transient errors.
+/// Cloning it is cheap and the clones share the connection.
+#[derive(Debug, Clone)]
+pub struct AuthClientSdk {
+    client: Client,
+    zkp: ZKP,
+    retry: RetryPolicy,
+    // realm is sent with every request if set, else the server's default realm is used
+    realm: Option<AsciiMetadataValue>,
+}
+
+impl AuthClientSdk {
+    /// new creates an SDK using the given channel and the default group parameters.
+    pub fn new(channel: Channel) -> Self {
+        let (g, h, p, q) = crate::default_cfg();
+        #[cfg(feature = "otel")]
+        let client = AuthClient::with_interceptor(channel, crate::telemetry::TraceContextInterceptor);
+        #[cfg(not(feature = "otel"))]
+        let client = AuthClient::new(channel);
+        AuthClientSdk { client, zkp: ZKP { g, h, p, q }, retry: RetryPolicy::default(), realm: None }
+    }
+
+    /// connect connects to the server at the endpoint, which carries the address and e.g. the TLS configuration.
+    pub async fn connect(endpoint: Endpoint) -> Result<Self, SdkError> {
+        Ok(Self::new(endpoint.connect().await?))
+    }
+
+    /// with_retry_policy sets how operations failing with transient errors are retried.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// with_realm makes all requests in the realm with the given id. An empty id selects the default realm.
+    /// Ids which cannot be sent as metadata are rejected.
+    pub fn with_realm(mut self, realm: &str) -> Result<Self, InvalidMetadataValue> {
+        let value = MetadataValue::try_from(realm)?;
+        self.realm = Some(value).filter(|_| !realm.is_empty());
+        Ok(self)
+    }
+
+    /// with_group sets the group parameters, which have to be the ones of the realm the requests are made in.
+    pub fn with_group(mut self, zkp: ZKP) -> Self {
+        self.zkp = zkp;
+        self
+    }
+
+    /// public_keys computes the public keys y1 = g^x mod p and y2 = h^x mod p of the secret x.
+    pub fn public_keys(&self, secret: &BigUint) -> PublicKeys {
+        PublicKeys::from_secret(&self.zkp, secret)
+    }
+
+    /// register registers the user with the public keys of the secret.
+    /// If a retried attempt fails with AlreadyRegistered, an earlier attempt may have registered the user.
+    pub async fn register(&self, user: &str, secret: &BigUint) -> Result<(), SdkError> {
+        let request = RegisterRequest::new(user, &self.public_keys(secret));
+        self.retry(|mut client| {
+            let request = request.clone();
+            async move { client.register(self.request(request)).await.map(|_| ()).map_err(SdkError::from) }
+        })
+        .await
+    }
+
+    /// login proves that the user knows the secret and starts a session.
+    pub async fn login(&self, user: &str, secret: &BigUint) -> Result<Session, SdkError> {
+        self.retry(|mut client| async move {
+            let proof = self.answer_challenge(&mut client, user, secret).await?;
+            let response = client.verify_authentication(self.request(AuthenticationAnswerRequest::from(proof))).await.map_err(answer_error)?.into_inner();
+            Ok(Session {
+                id: response.session_id,
+                user: user.to_string(),
+                expires_at: UNIX_EPOCH + Duration::from_secs(response.expires_at),
+            })
+        })
+        .await
+    }
+
+    /// rotate_keys replaces the public keys of the user with the ones of new_secret, proving knowledge of the
+    /// current secret.
+    pub async fn rotate_keys(&self, user: &str, secret: &BigUint, new_secret: &BigUint) -> Result<(), SdkError> {
+        let new_keys = self.public_keys(new_secret);
+        self.retry(|mut client| {
+            let new_keys = &new_keys;
+            async move {
+                let proof = self.answer_challenge(&mut client, user, secret).await?;
+                let request = RotateKeysRequest::with_proof(proof, new_keys);
+                client.rotate_keys(self.request(request)).await.map(|_| ()).map_err(answer_error)
+            }
+        })
+        .await
+    }
+
+    /// validate_session returns the details of the session with the given id.
+    pub async fn validate_session(&self, session_id: &str) -> Result<ServerSession, SdkError> {
+        let request = ValidateSessionRequest { session_id: session_id.to_string() };
+        let response = self
+            .retry(|mut client| {
+                let request = request.clone();
+                async move { client.validate_session(self.request(request)).await.map_err(SdkError::from) }
+            })
+            .await?
+            .into_inner();
+        let non_empty = |value: String| Some(value).filter(|value| !value.is_empty());
+        Ok(ServerSession {
+            user: response.user,
+            issued_at: UNIX_EPOCH + Duration::from_secs(response.issued_at),
+            expires_at: UNIX_EPOCH + Duration::from_secs(response.expires_at),
+            client: ClientMetadata { peer_addr: non_empty(response.peer_addr), user_agent: non_empty(response.user_agent) },
+        })
+    }
+
+    /// refresh_session extends the session and returns it with its new id and expiry.
+    pub async fn refresh_session(&self, session: &Session) -> Result<Session, SdkError> {
+        let request = RefreshSessionRequest { session_id: session.id.clone() };
+        let response = self
+            .retry(|mut client| {
+                let request = request.clone();
+                async move { client.refresh_session(self.request(request)).await.map_err(SdkError::from) }
+            })
+            .await?
+            .into_inner();
+        Ok(Session {
+            id: response.session_id,
+            user: session.user.clone(),
+            expires_at: UNIX_EPOCH + Duration::from_secs(response.expires_at),
+        })
+    }
+
+    /// logout ends the session with the given id. Ending a session which does not exist succeeds.
+    pub async fn logout(&self, session_id: &str) -> Result<(), SdkError> {
+        let request = LogoutRequest { session_id: session_id.to_string() };
+        self.retry(|mut client| {
+            let request = request.clone();
+            async move { client.logout(self.request(request)).await.map(|_| ()).map_err(SdkError::from) }
+        })
+        .await
+    }
+
+    /// answer_challenge sends a commitment for the user and solves the challenge the server answers with.
+    async fn answer_challenge(
+        &self,
+        client: &mut Client,
+        user: &str,
+        secret: &BigUint,
+    ) -> Result<Proof, SdkError> {
+        let (k, commitment) = self.zkp.commit(&mut rand::thread_rng());
+        let request = AuthenticationChallengeRequest::new(user, &commitment);
+        let challenge = Challenge::from(client.authentication_challenge(self.request(request)).await?.into_inner());
+        let s = self.zkp.solve(&k, &challenge.c, secret);
+        Ok(Proof { auth_id: challenge.auth_id, s })
+    }
+
+    /// request wraps the message in a request carrying the realm.
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        if let Some(realm) = &self.realm {
+            request.metadata_mut().insert(REALM, realm.clone());
+        }
+        request
+    }
+
+    /// retry runs the operation until it succeeds, fails with an error which is not transient or runs out of attempts.
+    async fn retry<T, F, Fut>(&self, operation: F) -> Result<T, SdkError>
+    where
+        F: Fn(Client) -> Fut,
+        Fut: Future<Output = Result<T, SdkError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation(self.client.clone()).await {
+                Err(err) if err.is_transient() && attempt < self.retry.max_attempts => {
+                    tokio::time::sleep(self.retry.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_error_from_status() {
+        assert!(matches!(SdkError::from(Status::already_exists("taken")), SdkError::AlreadyRegistered));
+        assert!(matches!(SdkError::from(Status::permission_denied("wrong")), SdkError::ProofRejected));
+        assert!(matches!(answer_error(Status::not_found("Auth ID")), SdkError::ChallengeExpired));
+
+        let mut status = Status::resource_exhausted("slow down");
+        status.metadata_mut().insert(RETRY_AFTER, MetadataValue::from(5));
+        assert!(matches!(SdkError::from(status), SdkError::RateLimited { retry_after: Some(d) } if d == Duration::from_secs(5)));
+
+        let err = SdkError::from(Status::unavailable("down"));
+        assert!(err.is_transient());
+        assert_eq!(err.code(), Some(Code::Unavailable));
+        assert!(!SdkError::from(Status::internal("bug")).is_transient());
+    }
+
+    #[tokio::test]
+    async fn test_realm() {
+        let sdk = AuthClientSdk::new(Endpoint::from_static("http://127.0.0.1:1").connect_lazy());
+        let request = sdk.request(LogoutRequest::default());
+        assert!(request.metadata().get(REALM).is_none());
+
+        let sdk = sdk.with_realm("shop").unwrap();
+        let request = sdk.request(LogoutRequest::default());
+        assert_eq!(request.metadata().get(REALM).unwrap(), "shop");
+        assert!(sdk.clone().with_realm("").unwrap().realm.is_none());
+        assert!(sdk.with_realm("sh\nop").is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let retry = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        assert_eq!(retry.backoff(1), Duration::from_millis(100));
+        assert_eq!(retry.backoff(2), Duration::from_millis(200));
+        assert_eq!(retry.backoff(3), Duration::from_millis(400));
+        assert_eq!(retry.backoff(4), Duration::from_millis(500));
+        assert_eq!(retry.backoff(100), Duration::from_millis(500));
+    }
+}
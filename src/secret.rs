@@ -0,0 +1,75 @@
+use argon2::Argon2;
+use num_bigint::BigUint;
+#[cfg(feature = "keystore")]
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Prefix of the salt used to derive a secret from a password, followed by the username.
+const PASSWORD_SALT_PREFIX: &str = "zkp-auth/";
+
+/// from_password derives the secret x of a user from a password with Argon2id, salted with the username so that
+/// users with the same password get different secrets. The result is reduced modulo the group order q.
+pub fn from_password(user: &str, password: &str, q: &BigUint) -> BigUint {
+    let salt = alloc::format!("{}{}", PASSWORD_SALT_PREFIX, user);
+    let mut output = [0u8; 64];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut output)
+        .expect("the salt and output lengths are within Argon2's limits");
+    BigUint::from_bytes_be(&output) % q
+}
+
+/// write_keyfile stores the secret x hex encoded in a new file which only its owner can read.
+/// It fails if the file already exists.
+#[cfg(feature = "keystore")]
+pub fn write_keyfile(path: impl AsRef<Path>, x: &BigUint) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", x.to_str_radix(16))
+}
+
+/// read_keyfile reads a secret written by write_keyfile.
+#[cfg(feature = "keystore")]
+pub fn read_keyfile(path: impl AsRef<Path>) -> io::Result<BigUint> {
+    let contents = fs::read_to_string(path)?;
+    BigUint::parse_bytes(contents.trim().as_bytes(), 16)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "key file does not contain a hex encoded secret"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{default_cfg, gen_random_number_below};
+
+    #[test]
+    fn test_from_password() {
+        let (_, _, _, q) = default_cfg();
+        let x = from_password("alice", "correct horse", &q);
+        assert!(x < q);
+        assert_eq!(x, from_password("alice", "correct horse", &q));
+        assert_ne!(x, from_password("alice", "battery staple", &q));
+        assert_ne!(x, from_password("bob", "correct horse", &q));
+    }
+
+    #[test]
+    #[cfg(feature = "keystore")]
+    fn test_keyfile() {
+        let (_, _, _, q) = default_cfg();
+        let x = gen_random_number_below(&q);
+        let path = std::env::temp_dir().join(format!("zkp-auth-key-{}", rand::random::<u64>()));
+
+        write_keyfile(&path, &x).unwrap();
+        assert_eq!(read_keyfile(&path).unwrap(), x);
+        // existing key files are never overwritten
+        assert_eq!(write_keyfile(&path, &x).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        fs::write(&path, "not a key").unwrap();
+        assert_eq!(read_keyfile(&path).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,223 @@
+use dashmap::{mapref::entry::Entry, DashMap};
+use rand::Rng;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Length of the randomly generated session ids.
+const SESSION_ID_LEN: usize = 32;
+
+/// ClientMetadata describes the client a session was issued to.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ClientMetadata {
+    // peer_addr is the remote address of the connection the session was created on, if known
+    pub peer_addr: Option<String>,
+    // user_agent is the user-agent reported by the client, if any
+    pub user_agent: Option<String>,
+}
+
+/// Session is an authenticated session of a user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    pub user: String,
+    pub issued_at: SystemTime,
+    pub expires_at: SystemTime,
+    pub client: ClientMetadata,
+}
+
+impl Session {
+    /// is_expired returns true if the session is no longer valid at the given moment.
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// SessionStore keeps the sessions issued by the server in memory, in a sharded map so that logins do not wait for
+/// each other.
+#[derive(Debug)]
+pub struct SessionStore {
+    // sessions is a map of session_id to session
+    sessions: DashMap<String, Session>,
+    // ttl is how long a session stays valid after it has been created or refreshed
+    ttl: Duration,
+}
+
+impl SessionStore {
+    /// new creates an empty store whose sessions expire after the given time-to-live.
+    pub fn new(ttl: Duration) -> Self {
+        SessionStore { sessions: DashMap::new(), ttl }
+    }
+
+    /// ttl returns how long a session stays valid after it has been created or refreshed.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// create starts a new session for the given user and returns its id together with the session.
+    pub fn create(&self, user: &str, client: ClientMetadata) -> (String, Session) {
+        let issued_at = SystemTime::now();
+        let session = Session { user: user.to_string(), issued_at, expires_at: issued_at + self.ttl, client };
+
+        loop {
+            let session_id = random_string(SESSION_ID_LEN);
+            if let Entry::Vacant(entry) = self.sessions.entry(session_id.clone()) {
+                entry.insert(session.clone());
+                return (session_id, session);
+            }
+        }
+    }
+
+    /// validate returns the session with the given id if it exists and has not expired.
+    pub fn validate(&self, session_id: &str) -> Option<Session> {
+        let now = SystemTime::now();
+        let session = self.sessions.get(session_id)?.clone();
+        if session.is_expired(now) {
+            self.sessions.remove_if(session_id, |_, session| session.is_expired(now));
+            return None;
+        }
+        Some(session)
+    }
+
+    /// refresh extends a valid session by the store's time-to-live and returns the updated session.
+    pub fn refresh(&self, session_id: &str) -> Option<Session> {
+        let now = SystemTime::now();
+        let mut session = self.sessions.get_mut(session_id)?;
+        if session.is_expired(now) {
+            drop(session);
+            self.sessions.remove_if(session_id, |_, session| session.is_expired(now));
+            return None;
+        }
+        session.expires_at = now + self.ttl;
+        Some(session.clone())
+    }
+
+    /// revoke removes the session with the given id and returns it if it existed.
+    pub fn revoke(&self, session_id: &str) -> Option<Session> {
+        self.sessions.remove(session_id).map(|(_, session)| session)
+    }
+
+    /// revoke_user removes all sessions of the given user and returns how many of them had not expired.
+    pub fn revoke_user(&self, user: &str) -> usize {
+        let now = SystemTime::now();
+        let mut revoked = 0;
+        self.sessions.retain(|_, session| {
+            if session.user != user {
+                return true;
+            }
+            if !session.is_expired(now) {
+                revoked += 1;
+            }
+            false
+        });
+        revoked
+    }
+
+    /// count_user returns how many sessions of the given user have not expired.
+    pub fn count_user(&self, user: &str) -> usize {
+        let now = SystemTime::now();
+        self.sessions.iter().filter(|session| session.user == user && !session.is_expired(now)).count()
+    }
+
+    /// reap_expired removes all expired sessions and returns how many were removed.
+    pub fn reap_expired(&self) -> usize {
+        let now = SystemTime::now();
+        let mut reaped = 0;
+        self.sessions.retain(|_, session| {
+            reaped += usize::from(session.is_expired(now));
+            !session.is_expired(now)
+        });
+        reaped
+    }
+
+    /// is_healthy returns true if the store can serve requests. The locks of its shards are not poisoned by panics,
+    /// so the memory store always can.
+    pub fn is_healthy(&self) -> bool {
+        true
+    }
+}
+
+/// unix_seconds converts a point in time to whole seconds since the Unix epoch.
+pub fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or_default()
+}
+
+/// random_string is used to generate a random string of a given size.
+fn random_string(size: usize) -> String {
+    rand::thread_rng()
+        .sample_iter(rand::distributions::Alphanumeric)
+        .take(size)
+        .map(char::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_create_and_validate() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let client = ClientMetadata { peer_addr: Some("127.0.0.1:1234".to_string()), user_agent: None };
+
+        let (session_id, session) = store.create("alice", client.clone());
+        assert_eq!(session_id.len(), SESSION_ID_LEN);
+        assert_eq!(session.user, "alice");
+        assert_eq!(session.client, client);
+        assert_eq!(session.expires_at, session.issued_at + Duration::from_secs(60));
+
+        assert_eq!(store.validate(&session_id), Some(session));
+        assert_eq!(store.validate("unknown"), None);
+    }
+
+    #[test]
+    fn test_expired_session() {
+        let store = SessionStore::new(Duration::ZERO);
+        let (session_id, _) = store.create("alice", ClientMetadata::default());
+
+        assert_eq!(store.validate(&session_id), None);
+        assert_eq!(store.refresh(&session_id), None);
+    }
+
+    #[test]
+    fn test_refresh() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session_id, session) = store.create("alice", ClientMetadata::default());
+
+        let refreshed = store.refresh(&session_id).unwrap();
+        assert_eq!(refreshed.issued_at, session.issued_at);
+        assert!(refreshed.expires_at >= session.expires_at);
+        assert_eq!(store.refresh("unknown"), None);
+    }
+
+    #[test]
+    fn test_revoke() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (session_id, _) = store.create("alice", ClientMetadata::default());
+
+        assert!(store.revoke(&session_id).is_some());
+        assert!(store.revoke(&session_id).is_none());
+        assert_eq!(store.validate(&session_id), None);
+    }
+
+    #[test]
+    fn test_revoke_user() {
+        let store = SessionStore::new(Duration::from_secs(60));
+        let (alice_1, _) = store.create("alice", ClientMetadata::default());
+        store.create("alice", ClientMetadata::default());
+        let (bob, _) = store.create("bob", ClientMetadata::default());
+
+        assert_eq!(store.count_user("alice"), 2);
+        assert_eq!(store.revoke_user("alice"), 2);
+        assert_eq!(store.count_user("alice"), 0);
+        assert_eq!(store.validate(&alice_1), None);
+        assert!(store.validate(&bob).is_some());
+    }
+
+    #[test]
+    fn test_reap_expired() {
+        let store = SessionStore::new(Duration::ZERO);
+        store.create("alice", ClientMetadata::default());
+        store.create("bob", ClientMetadata::default());
+
+        assert_eq!(store.reap_expired(), 2);
+        assert_eq!(store.reap_expired(), 0);
+    }
+}
@@ -0,0 +1,119 @@
+use opentelemetry::{
+    propagation::{Extractor, Injector, TextMapPropagator},
+    trace::{TraceError, TracerProvider as _},
+    Context, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use tonic::{
+    metadata::{KeyAndValueRef, MetadataKey, MetadataMap, MetadataValue},
+    service::Interceptor,
+    Request, Status,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// MetadataInjector writes the fields of a propagator into gRPC metadata.
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (MetadataKey::from_bytes(key.as_bytes()), MetadataValue::try_from(value)) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// MetadataExtractor reads the fields of a propagator from gRPC metadata.
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter_map(|entry| match entry {
+                KeyAndValueRef::Ascii(key, _) => Some(key.as_str()),
+                KeyAndValueRef::Binary(..) => None,
+            })
+            .collect()
+    }
+}
+
+/// inject_context adds the trace context of the current span to the metadata as W3C `traceparent` and `tracestate`
+/// entries. Nothing is added unless the tracing subscriber has an OpenTelemetry layer.
+pub fn inject_context(metadata: &mut MetadataMap) {
+    let cx = tracing::Span::current().context();
+    TraceContextPropagator::new().inject_context(&cx, &mut MetadataInjector(metadata));
+}
+
+/// extract_context returns the trace context the caller sent in the metadata, or an empty context if there is none.
+pub fn extract_context(metadata: &MetadataMap) -> Context {
+    TraceContextPropagator::new().extract(&MetadataExtractor(metadata))
+}
+
+/// TraceContextInterceptor adds the trace context of the current span to every request of a client.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TraceContextInterceptor;
+
+impl Interceptor for TraceContextInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        inject_context(request.metadata_mut());
+        Ok(request)
+    }
+}
+
+/// otlp_tracer_provider creates a provider exporting spans in batches to the OTLP/gRPC collector at the endpoint,
+/// e.g. `http://localhost:4317`. It has to be created within a Tokio runtime and shut down before the process exits
+/// so that the last batch is sent.
+pub fn otlp_tracer_provider(endpoint: &str, service_name: &'static str) -> Result<TracerProvider, TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder().with_tonic().with_endpoint(endpoint).build()?;
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build())
+}
+
+/// tracer returns the tracer of the provider for the given instrumentation scope.
+pub fn tracer(provider: &TracerProvider, name: &'static str) -> Tracer {
+    provider.tracer(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_propagation() {
+        // without an OpenTelemetry layer there is nothing to propagate
+        let mut metadata = MetadataMap::new();
+        inject_context(&mut metadata);
+        assert!(metadata.get("traceparent").is_none());
+        assert!(!extract_context(&metadata).span().span_context().is_valid());
+
+        let provider = TracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(tracer(&provider, "test")));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("login");
+            let _entered = span.enter();
+            let trace_id = span.context().span().span_context().trace_id();
+
+            let request = TraceContextInterceptor.call(Request::new(())).unwrap();
+            let traceparent = request.metadata().get("traceparent").unwrap().to_str().unwrap();
+            assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+            let cx = extract_context(request.metadata());
+            assert_eq!(cx.span().span_context().trace_id(), trace_id);
+            assert!(cx.span().span_context().is_remote());
+        });
+    }
+}
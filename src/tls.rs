@@ -0,0 +1,46 @@
+use std::{fs, io, path::Path};
+use tonic::transport::{Certificate, Identity};
+#[cfg(feature = "grpc-client")]
+use tonic::transport::ClientTlsConfig;
+#[cfg(feature = "grpc-server")]
+use tonic::transport::ServerTlsConfig;
+
+/// server_config creates the TLS configuration of the server from a PEM certificate chain and private key.
+/// If a client CA certificate is given, clients have to present a certificate signed by it (mutual TLS).
+#[cfg(feature = "grpc-server")]
+pub fn server_config(cert: &Path, key: &Path, client_ca: Option<&Path>) -> io::Result<ServerTlsConfig> {
+    let mut config = ServerTlsConfig::new().identity(identity(cert, key)?);
+    if let Some(client_ca) = client_ca {
+        config = config.client_ca_root(Certificate::from_pem(fs::read(client_ca)?));
+    }
+    Ok(config)
+}
+
+/// client_config creates the TLS configuration of the client. The server certificate is checked against the
+/// given CA certificate, or against the system's trusted roots if there is none. If a certificate and key are
+/// given, they are presented to servers requiring mutual TLS. The domain name overrides the host name the
+/// server certificate is checked for, which defaults to the host of the server address.
+#[cfg(feature = "grpc-client")]
+pub fn client_config(
+    ca: Option<&Path>,
+    cert_and_key: Option<(&Path, &Path)>,
+    domain: Option<&str>,
+) -> io::Result<ClientTlsConfig> {
+    let mut config = ClientTlsConfig::new();
+    config = match ca {
+        Some(ca) => config.ca_certificate(Certificate::from_pem(fs::read(ca)?)),
+        None => config.with_native_roots(),
+    };
+    if let Some((cert, key)) = cert_and_key {
+        config = config.identity(identity(cert, key)?);
+    }
+    if let Some(domain) = domain {
+        config = config.domain_name(domain);
+    }
+    Ok(config)
+}
+
+/// identity loads a PEM certificate chain together with its private key.
+fn identity(cert: &Path, key: &Path) -> io::Result<Identity> {
+    Ok(Identity::from_pem(fs::read(cert)?, fs::read(key)?))
+}
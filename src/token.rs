@@ -0,0 +1,573 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ed25519_dalek::pkcs8::{DecodePrivateKey, DecodePublicKey};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+/// The authentication method recorded in the `amr` claim of every token.
+pub const AUTH_METHOD: &str = "zkp-chaum-pedersen";
+
+/// How long verifiers may cache the JWKS document. A new key is published for this long before it signs tokens, so
+/// that every verifier knows it by then.
+pub const JWKS_MAX_AGE: Duration = Duration::from_secs(300);
+
+/// TokenError is returned when a key cannot be loaded or a token cannot be issued or verified.
+#[derive(Debug)]
+pub enum TokenError {
+    // Io means the key file could not be read
+    Io(std::io::Error),
+    // UnsupportedKey means the key is neither an Ed25519 nor a P-256 key in PEM format
+    UnsupportedKey,
+    // UnknownKeyId means the token was signed with a key that is not known to the verifier
+    UnknownKeyId(Option<String>),
+    // Jwt means the token could not be signed, or its signature or claims are not valid
+    Jwt(jsonwebtoken::errors::Error),
+}
+
+impl fmt::Display for TokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TokenError::Io(err) => write!(f, "could not read key: {}", err),
+            TokenError::UnsupportedKey => write!(f, "key must be an Ed25519 or P-256 key in PEM format"),
+            TokenError::UnknownKeyId(kid) => write!(f, "unknown key id: {:?}", kid),
+            TokenError::Jwt(err) => write!(f, "invalid token: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for TokenError {}
+
+impl From<std::io::Error> for TokenError {
+    fn from(err: std::io::Error) -> Self {
+        TokenError::Io(err)
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for TokenError {
+    fn from(err: jsonwebtoken::errors::Error) -> Self {
+        TokenError::Jwt(err)
+    }
+}
+
+/// Claims are the contents of a session token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Claims {
+    // sub is the user the session belongs to
+    pub sub: String,
+    // amr lists the methods the user authenticated with
+    pub amr: Vec<String>,
+    // grp identifies the group parameters the user proved knowledge of the secret in
+    pub grp: String,
+    // realm is the realm the user belongs to; it is left out for the default realm
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub realm: String,
+    // iat is the Unix time in seconds at which the session was created
+    pub iat: u64,
+    // exp is the Unix time in seconds at which the token expires
+    pub exp: u64,
+    // jti is the id of the session on the server which issued the token
+    pub jti: String,
+}
+
+/// Jwk is the public half of a signing key as a JSON Web Key (RFC 7517).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub x: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub y: Option<String>,
+    pub kid: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub use_: String,
+}
+
+/// Jwks is a JSON Web Key Set, the document services fetch to verify session tokens.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwks {
+    pub keys: Vec<Jwk>,
+}
+
+/// VerifyingKey checks the signature of session tokens. It only holds public key material so it can be
+/// handed to services which verify tokens offline.
+#[derive(Clone)]
+pub struct VerifyingKey {
+    kid: String,
+    alg: Algorithm,
+    decoding: DecodingKey,
+    jwk: Jwk,
+}
+
+impl VerifyingKey {
+    /// from_public_pem loads an Ed25519 or P-256 public key in SubjectPublicKeyInfo PEM format.
+    pub fn from_public_pem(kid: &str, pem: &str) -> Result<Self, TokenError> {
+        if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            return Ok(Self::ed25519(kid, &key));
+        }
+        if let Ok(key) = p256::PublicKey::from_public_key_pem(pem) {
+            return Ok(Self::p256(kid, &key));
+        }
+        Err(TokenError::UnsupportedKey)
+    }
+
+    /// load reads a public key from the given PEM file.
+    pub fn load(kid: &str, path: impl AsRef<Path>) -> Result<Self, TokenError> {
+        Self::from_public_pem(kid, &fs::read_to_string(path)?)
+    }
+
+    fn ed25519(kid: &str, key: &ed25519_dalek::VerifyingKey) -> Self {
+        let x = URL_SAFE_NO_PAD.encode(key.as_bytes());
+        VerifyingKey {
+            kid: kid.to_string(),
+            alg: Algorithm::EdDSA,
+            decoding: DecodingKey::from_ed_components(&x).expect("valid Ed25519 public key"),
+            jwk: Jwk {
+                kty: "OKP".to_string(),
+                crv: "Ed25519".to_string(),
+                x,
+                y: None,
+                kid: kid.to_string(),
+                alg: "EdDSA".to_string(),
+                use_: "sig".to_string(),
+            },
+        }
+    }
+
+    fn p256(kid: &str, key: &p256::PublicKey) -> Self {
+        let point = key.to_encoded_point(false);
+        let x = URL_SAFE_NO_PAD.encode(point.x().expect("uncompressed point"));
+        let y = URL_SAFE_NO_PAD.encode(point.y().expect("uncompressed point"));
+        VerifyingKey {
+            kid: kid.to_string(),
+            alg: Algorithm::ES256,
+            decoding: DecodingKey::from_ec_components(&x, &y).expect("valid P-256 public key"),
+            jwk: Jwk {
+                kty: "EC".to_string(),
+                crv: "P-256".to_string(),
+                x,
+                y: Some(y),
+                kid: kid.to_string(),
+                alg: "ES256".to_string(),
+                use_: "sig".to_string(),
+            },
+        }
+    }
+
+    /// from_jwk loads a public key published in a JWKS document.
+    pub fn from_jwk(jwk: &Jwk) -> Result<Self, TokenError> {
+        match (jwk.kty.as_str(), jwk.crv.as_str(), &jwk.y) {
+            ("OKP", "Ed25519", None) => {
+                let x: [u8; 32] = URL_SAFE_NO_PAD
+                    .decode(&jwk.x)
+                    .ok()
+                    .and_then(|x| x.try_into().ok())
+                    .ok_or(TokenError::UnsupportedKey)?;
+                let key = ed25519_dalek::VerifyingKey::from_bytes(&x).map_err(|_| TokenError::UnsupportedKey)?;
+                Ok(Self::ed25519(&jwk.kid, &key))
+            }
+            ("EC", "P-256", Some(y)) => {
+                let x = URL_SAFE_NO_PAD.decode(&jwk.x).map_err(|_| TokenError::UnsupportedKey)?;
+                let y = URL_SAFE_NO_PAD.decode(y).map_err(|_| TokenError::UnsupportedKey)?;
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(TokenError::UnsupportedKey);
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(x.as_slice().into(), y.as_slice().into(), false);
+                let key = p256::PublicKey::from_sec1_bytes(point.as_bytes()).map_err(|_| TokenError::UnsupportedKey)?;
+                Ok(Self::p256(&jwk.kid, &key))
+            }
+            _ => Err(TokenError::UnsupportedKey),
+        }
+    }
+
+    /// jwk returns the key as a JSON Web Key.
+    pub fn jwk(&self) -> &Jwk {
+        &self.jwk
+    }
+
+    /// kid returns the id of the key, carried in the header of the tokens it verifies.
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    /// alg returns the signature algorithm of the key.
+    pub fn alg(&self) -> Algorithm {
+        self.alg
+    }
+
+    /// verify checks the signature and expiry of a token and returns its claims.
+    pub fn verify(&self, token: &str) -> Result<Claims, TokenError> {
+        let header = jsonwebtoken::decode_header(token)?;
+        if header.kid.as_deref() != Some(self.kid.as_str()) {
+            return Err(TokenError::UnknownKeyId(header.kid));
+        }
+
+        let mut validation = Validation::new(self.alg);
+        validation.leeway = 0;
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        let data = jsonwebtoken::decode::<Claims>(token, &self.decoding, &validation)?;
+        Ok(data.claims)
+    }
+}
+
+impl fmt::Debug for VerifyingKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifyingKey").field("kid", &self.kid).field("alg", &self.alg).finish()
+    }
+}
+
+/// SigningKey signs the session tokens issued by the server.
+#[derive(Clone)]
+pub struct SigningKey {
+    encoding: EncodingKey,
+    verifying: VerifyingKey,
+}
+
+impl SigningKey {
+    /// from_pkcs8_pem loads an Ed25519 or P-256 private key in PKCS#8 PEM format.
+    pub fn from_pkcs8_pem(kid: &str, pem: &str) -> Result<Self, TokenError> {
+        if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+            return Ok(SigningKey {
+                encoding: EncodingKey::from_ed_pem(pem.as_bytes())?,
+                verifying: VerifyingKey::ed25519(kid, &key.verifying_key()),
+            });
+        }
+        if let Ok(key) = p256::SecretKey::from_pkcs8_pem(pem) {
+            return Ok(SigningKey {
+                encoding: EncodingKey::from_ec_pem(pem.as_bytes())?,
+                verifying: VerifyingKey::p256(kid, &key.public_key()),
+            });
+        }
+        Err(TokenError::UnsupportedKey)
+    }
+
+    /// load reads a private key from the given PEM file.
+    pub fn load(kid: &str, path: impl AsRef<Path>) -> Result<Self, TokenError> {
+        Self::from_pkcs8_pem(kid, &fs::read_to_string(path)?)
+    }
+
+    /// verifying_key returns the public half of the key.
+    pub fn verifying_key(&self) -> &VerifyingKey {
+        &self.verifying
+    }
+
+    /// sign creates a token carrying the given claims.
+    pub fn sign(&self, claims: &Claims) -> Result<String, TokenError> {
+        let mut header = Header::new(self.verifying.alg);
+        header.kid = Some(self.verifying.kid.clone());
+        Ok(jsonwebtoken::encode(&header, claims, &self.encoding)?)
+    }
+}
+
+impl fmt::Debug for SigningKey {
+    // The private key is deliberately left out.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SigningKey").field("verifying", &self.verifying).finish()
+    }
+}
+
+/// RetiredKey is a key which no longer signs tokens but still verifies the tokens it signed.
+#[derive(Debug, Clone)]
+struct RetiredKey {
+    key: VerifyingKey,
+    retired_at: SystemTime,
+}
+
+/// PendingKey is a key which is published before it signs tokens, so that the verifiers caching the JWKS document
+/// know it by the time they see the first token it signed.
+#[derive(Debug, Clone)]
+struct PendingKey {
+    key: SigningKey,
+    active_at: SystemTime,
+}
+
+/// KeyRing holds the key currently used to sign tokens together with the previous keys, which are kept
+/// so that rotating the signing key does not invalidate the tokens that are still outstanding, and the next keys,
+/// which are published ahead of signing.
+#[derive(Debug, Clone)]
+pub struct KeyRing {
+    current: SigningKey,
+    // pending are the keys waiting to sign, ordered by key id
+    pending: Vec<PendingKey>,
+    previous: Vec<RetiredKey>,
+}
+
+impl KeyRing {
+    /// new creates a key ring signing with the given key.
+    pub fn new(current: SigningKey) -> Self {
+        KeyRing { current, pending: Vec::new(), previous: Vec::new() }
+    }
+
+    /// load_dir creates a key ring from a directory of PKCS#8 PEM files. Every file is a key whose id is the
+    /// file name without extension, and the key with the greatest id signs tokens once it has been published for the
+    /// activation delay, so naming the files by date (e.g. `2024-06.pem`) makes the newest key the current one. The
+    /// key with the greatest id whose file is older than the delay signs right away; newer keys are published now and
+    /// sign after the delay. If no file is that old, the key with the greatest id signs right away. The other keys
+    /// only verify tokens.
+    pub fn load_dir(dir: impl AsRef<Path>, activation_delay: Duration) -> Result<Self, TokenError> {
+        let now = SystemTime::now();
+        let mut keys = load_keys(dir.as_ref())?;
+        let last = keys.len().checked_sub(1).ok_or(TokenError::UnsupportedKey)?;
+        let active = keys.iter().rposition(|(_, modified)| *modified + activation_delay <= now).unwrap_or(last);
+        let pending = keys.split_off(active + 1);
+        let (current, _) = keys.pop().expect("the active key is loaded");
+        let mut ring = KeyRing::new(current);
+        ring.pending = pending.into_iter().map(|(key, _)| PendingKey { key, active_at: now + activation_delay }).collect();
+        ring.previous = keys
+            .into_iter()
+            .map(|(key, _)| RetiredKey { key: key.verifying, retired_at: now })
+            .collect();
+        Ok(ring)
+    }
+
+    /// reload_dir publishes the keys in the directory with greater ids than the current key which are new to the
+    /// ring, and lets them sign once they have been published for the activation delay. It rotates to the pending
+    /// key with the greatest id whose time has come, if any, and returns true if the key was rotated.
+    pub fn reload_dir(&mut self, dir: impl AsRef<Path>, activation_delay: Duration) -> Result<bool, TokenError> {
+        let now = SystemTime::now();
+        for (key, _) in load_keys(dir.as_ref())? {
+            let kid = key.verifying.kid.as_str();
+            if kid > self.current.verifying.kid.as_str() && !self.pending.iter().any(|pending| pending.key.verifying.kid == kid) {
+                self.pending.push(PendingKey { key, active_at: now + activation_delay });
+            }
+        }
+        self.pending.sort_by(|a, b| a.key.verifying.kid.cmp(&b.key.verifying.kid));
+
+        let Some(newest) = self.pending.iter().rposition(|pending| pending.active_at <= now) else {
+            return Ok(false);
+        };
+        // Pending keys with smaller ids than the new current key would never sign.
+        let next = self.pending.drain(..=newest).next_back().expect("the newest pending key is drained").key;
+        self.rotate(next);
+        Ok(true)
+    }
+
+    /// current returns the key which signs new tokens.
+    pub fn current(&self) -> &SigningKey {
+        &self.current
+    }
+
+    /// rotate makes the given key sign new tokens. The key it replaces keeps verifying tokens until pruned.
+    pub fn rotate(&mut self, next: SigningKey) {
+        let retired = std::mem::replace(&mut self.current, next);
+        self.previous.retain(|previous| previous.key.kid != self.current.verifying.kid);
+        self.previous.push(RetiredKey { key: retired.verifying, retired_at: SystemTime::now() });
+    }
+
+    /// prune forgets the previous keys retired more than retention ago and returns how many were removed.
+    /// The retention must be at least the lifetime of a token for outstanding tokens to stay valid.
+    pub fn prune(&mut self, retention: Duration) -> usize {
+        let now = SystemTime::now();
+        let before = self.previous.len();
+        self.previous
+            .retain(|previous| now.duration_since(previous.retired_at).unwrap_or_default() < retention);
+        before - self.previous.len()
+    }
+
+    /// verify checks a token signed by the current or one of the previous keys and returns its claims.
+    pub fn verify(&self, token: &str) -> Result<Claims, TokenError> {
+        let kid = jsonwebtoken::decode_header(token)?.kid;
+        std::iter::once(&self.current.verifying)
+            .chain(self.previous.iter().map(|previous| &previous.key))
+            .find(|key| Some(key.kid.as_str()) == kid.as_deref())
+            .ok_or(TokenError::UnknownKeyId(kid))?
+            .verify(token)
+    }
+
+    /// jwks returns the current, pending and previous keys as a JSON Web Key Set.
+    pub fn jwks(&self) -> Jwks {
+        let keys = std::iter::once(&self.current.verifying)
+            .chain(self.pending.iter().map(|pending| pending.key.verifying_key()))
+            .chain(self.previous.iter().map(|previous| &previous.key))
+            .map(|key| key.jwk.clone())
+            .collect();
+        Jwks { keys }
+    }
+}
+
+/// load_keys loads all PEM files in a directory as signing keys, sorted by key id, together with the time their files
+/// were last modified.
+fn load_keys(dir: &Path) -> Result<Vec<(SigningKey, SystemTime)>, TokenError> {
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("pem") {
+            continue;
+        }
+        let kid = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        keys.push((SigningKey::load(&kid, &path)?, fs::metadata(&path)?.modified()?));
+    }
+    keys.sort_by(|(a, _), (b, _)| a.verifying.kid.cmp(&b.verifying.kid));
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ed25519_dalek::pkcs8::{EncodePrivateKey, EncodePublicKey};
+    use p256::pkcs8::LineEnding;
+    use rand::rngs::OsRng;
+
+    fn claims(exp: u64) -> Claims {
+        Claims {
+            sub: "alice".to_string(),
+            amr: vec![AUTH_METHOD.to_string()],
+            grp: crate::DEFAULT_GROUP_ID.to_string(),
+            realm: String::new(),
+            iat: 0,
+            exp,
+            jti: "session".to_string(),
+        }
+    }
+
+    fn in_one_hour() -> u64 {
+        jsonwebtoken::get_current_timestamp() + 3600
+    }
+
+    fn ed25519_pem() -> (String, String) {
+        let key = ed25519_dalek::SigningKey::from_bytes(&rand::random());
+        let private = key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+        let public = key.verifying_key().to_public_key_pem(LineEnding::LF).unwrap();
+        (private, public)
+    }
+
+    fn p256_pem() -> (String, String) {
+        let key = p256::SecretKey::random(&mut OsRng);
+        let private = key.to_pkcs8_pem(LineEnding::LF).unwrap().to_string();
+        let public = key.public_key().to_public_key_pem(LineEnding::LF).unwrap();
+        (private, public)
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        for (private, public) in [ed25519_pem(), p256_pem()] {
+            let key = SigningKey::from_pkcs8_pem("key-1", &private).unwrap();
+            let claims = claims(in_one_hour());
+            let token = key.sign(&claims).unwrap();
+
+            // both the signing key and the public key loaded separately accept the token
+            assert_eq!(key.verifying_key().verify(&token).unwrap(), claims);
+            let verifier = VerifyingKey::from_public_pem("key-1", &public).unwrap();
+            assert_eq!(verifier.verify(&token).unwrap(), claims);
+        }
+
+        // the realm is left out of the tokens of the default realm only
+        let key = SigningKey::from_pkcs8_pem("key-1", &ed25519_pem().0).unwrap();
+        let payload = |claims: &Claims| {
+            let token = key.sign(claims).unwrap();
+            String::from_utf8(URL_SAFE_NO_PAD.decode(token.split('.').nth(1).unwrap()).unwrap()).unwrap()
+        };
+        assert!(!payload(&claims(in_one_hour())).contains("realm"));
+        let claims = Claims { realm: "shop".to_string(), ..claims(in_one_hour()) };
+        assert!(payload(&claims).contains("\"realm\":\"shop\""));
+        assert_eq!(key.verifying_key().verify(&key.sign(&claims).unwrap()).unwrap(), claims);
+    }
+
+    #[test]
+    fn test_algorithms() {
+        let key = SigningKey::from_pkcs8_pem("ed", &ed25519_pem().0).unwrap();
+        assert_eq!(key.verifying_key().alg(), Algorithm::EdDSA);
+        let key = SigningKey::from_pkcs8_pem("ec", &p256_pem().0).unwrap();
+        assert_eq!(key.verifying_key().alg(), Algorithm::ES256);
+        assert!(matches!(SigningKey::from_pkcs8_pem("bad", "not a key"), Err(TokenError::UnsupportedKey)));
+    }
+
+    #[test]
+    fn test_expired_token() {
+        let key = SigningKey::from_pkcs8_pem("key-1", &ed25519_pem().0).unwrap();
+        let token = key.sign(&claims(1)).unwrap();
+        assert!(matches!(key.verifying_key().verify(&token), Err(TokenError::Jwt(_))));
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let key = SigningKey::from_pkcs8_pem("key-1", &ed25519_pem().0).unwrap();
+        let other = SigningKey::from_pkcs8_pem("key-1", &ed25519_pem().0).unwrap();
+        let token = key.sign(&claims(in_one_hour())).unwrap();
+        assert!(matches!(other.verifying_key().verify(&token), Err(TokenError::Jwt(_))));
+
+        let renamed = SigningKey::from_pkcs8_pem("key-2", &ed25519_pem().0).unwrap();
+        assert!(matches!(renamed.verifying_key().verify(&token), Err(TokenError::UnknownKeyId(_))));
+    }
+
+    #[test]
+    fn test_jwk_roundtrip() {
+        for (private, _) in [ed25519_pem(), p256_pem()] {
+            let key = SigningKey::from_pkcs8_pem("key-1", &private).unwrap();
+            let token = key.sign(&claims(in_one_hour())).unwrap();
+
+            let jwk = key.verifying_key().jwk().clone();
+            assert_eq!(jwk.kid, "key-1");
+            assert_eq!(jwk.use_, "sig");
+            let verifier = VerifyingKey::from_jwk(&jwk).unwrap();
+            assert_eq!(verifier.verify(&token).unwrap().sub, "alice");
+        }
+    }
+
+    #[test]
+    fn test_key_ring_rotation() {
+        let first = SigningKey::from_pkcs8_pem("2024-01", &ed25519_pem().0).unwrap();
+        let second = SigningKey::from_pkcs8_pem("2024-02", &p256_pem().0).unwrap();
+        let mut ring = KeyRing::new(first.clone());
+        let old_token = ring.current().sign(&claims(in_one_hour())).unwrap();
+
+        ring.rotate(second);
+        let new_token = ring.current().sign(&claims(in_one_hour())).unwrap();
+        assert_eq!(ring.current().verifying_key().kid(), "2024-02");
+
+        // tokens signed before the rotation remain valid and both keys are published
+        assert!(ring.verify(&old_token).is_ok());
+        assert!(ring.verify(&new_token).is_ok());
+        let kids: Vec<String> = ring.jwks().keys.into_iter().map(|jwk| jwk.kid).collect();
+        assert_eq!(kids, vec!["2024-02", "2024-01"]);
+
+        // the previous key is kept for the retention period only
+        assert_eq!(ring.prune(Duration::from_secs(60)), 0);
+        assert_eq!(ring.prune(Duration::ZERO), 1);
+        assert!(matches!(ring.verify(&old_token), Err(TokenError::UnknownKeyId(_))));
+        assert!(ring.verify(&new_token).is_ok());
+    }
+
+    #[test]
+    fn test_key_ring_dir() {
+        let dir = std::env::temp_dir().join(format!("zkp-auth-keys-{}", rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        let an_hour_ago = SystemTime::now() - Duration::from_secs(3600);
+        for (kid, pem) in [("2024-01", ed25519_pem().0), ("2024-02", p256_pem().0)] {
+            let path = dir.join(format!("{}.pem", kid));
+            fs::write(&path, pem).unwrap();
+            fs::File::options().write(true).open(&path).unwrap().set_modified(an_hour_ago).unwrap();
+        }
+        fs::write(dir.join("2024-03.pem"), ed25519_pem().0).unwrap();
+        fs::write(dir.join("README"), "not a key").unwrap();
+        let kids = |ring: &KeyRing| ring.jwks().keys.into_iter().map(|jwk| jwk.kid).collect::<Vec<_>>();
+
+        // a key dropped into the directory less than the delay ago is published but does not sign yet
+        let mut ring = KeyRing::load_dir(&dir, Duration::from_secs(60)).unwrap();
+        assert_eq!(ring.current().verifying_key().kid(), "2024-02");
+        assert_eq!(kids(&ring), ["2024-02", "2024-03", "2024-01"]);
+        assert!(!ring.reload_dir(&dir, Duration::from_secs(60)).unwrap());
+        assert_eq!(ring.current().verifying_key().kid(), "2024-02");
+
+        // once the delay has passed it signs
+        let mut ring = KeyRing::load_dir(&dir, Duration::ZERO).unwrap();
+        assert_eq!(ring.current().verifying_key().kid(), "2024-03");
+        assert!(!ring.reload_dir(&dir, Duration::ZERO).unwrap());
+        let delay = Duration::from_millis(50);
+        fs::write(dir.join("2024-04.pem"), ed25519_pem().0).unwrap();
+        assert!(!ring.reload_dir(&dir, delay).unwrap());
+        assert_eq!(ring.current().verifying_key().kid(), "2024-03");
+        assert_eq!(kids(&ring), ["2024-03", "2024-04", "2024-01", "2024-02"]);
+        std::thread::sleep(delay);
+        assert!(ring.reload_dir(&dir, delay).unwrap());
+        assert_eq!(ring.current().verifying_key().kid(), "2024-04");
+        assert_eq!(kids(&ring), ["2024-04", "2024-01", "2024-02", "2024-03"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
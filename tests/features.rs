@@ -0,0 +1,69 @@
+use std::{env, path::Path, process::Command};
+
+/// FEATURE_SETS are the feature combinations the library and the binaries they enable have to build with.
+const FEATURE_SETS: &[&str] = &[
+    "",
+    "std",
+    "modp",
+    "ec",
+    "std,modp",
+    "ec,std",
+    "password",
+    "keystore",
+    "grpc-client",
+    "grpc-server",
+    "grpc-client,grpc-server",
+    "grpc-client,keystore,cli",
+    "grpc-server,cli",
+    "otel",
+    "grpc-client,otel",
+    "grpc-server,otel,cli",
+];
+
+/// NO_STD_TARGET is a target without the standard library which the proofs have to build for.
+const NO_STD_TARGET: &str = "thumbv7em-none-eabihf";
+
+/// check runs cargo check with only the given features enabled and any further arguments. Warnings are errors, so
+/// that code left unused by a feature combination is caught.
+fn check(features: &str, args: &[&str]) -> bool {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let status = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_string()))
+        .current_dir(manifest_dir)
+        .args(["check", "--quiet", "--no-default-features", "--features", features])
+        .args(args)
+        // A separate target directory so that the check does not wait for the lock held by the running test build.
+        .env("CARGO_TARGET_DIR", manifest_dir.join("target").join("feature-check"))
+        .env("RUSTFLAGS", "-D warnings")
+        .status()
+        .expect("failed to run cargo");
+    status.success()
+}
+
+/// target_installed returns true if the standard library of the target is installed for the current toolchain.
+fn target_installed(target: &str) -> bool {
+    let output = Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+        .args(["--print", "sysroot"])
+        .output()
+        .expect("failed to run rustc");
+    let sysroot = String::from_utf8_lossy(&output.stdout);
+    Path::new(sysroot.trim()).join("lib").join("rustlib").join(target).exists()
+}
+
+/// test_feature_combinations_build checks every feature set in a cargo build of its own, which takes a while, so it
+/// only runs when asked for with `cargo test --test features -- --ignored test_feature_combinations_build`, as CI
+/// does.
+#[test]
+#[ignore = "checks every feature set with a separate cargo build"]
+fn test_feature_combinations_build() {
+    let failed: Vec<_> = FEATURE_SETS.iter().filter(|features| !check(features, &["--lib", "--bins"])).collect();
+    assert!(failed.is_empty(), "the crate does not build with the features {:?}", failed);
+}
+
+/// test_no_std_build needs the target installed, so it only runs when asked for with
+/// `cargo test --test features -- --ignored test_no_std_build`, as CI does.
+#[test]
+#[ignore = "requires rustup target add thumbv7em-none-eabihf"]
+fn test_no_std_build() {
+    assert!(target_installed(NO_STD_TARGET), "install the target with `rustup target add {}`", NO_STD_TARGET);
+    assert!(check("modp,ec", &["--lib", "--target", NO_STD_TARGET]), "the proofs do not build for {}", NO_STD_TARGET);
+}
@@ -0,0 +1,108 @@
+use zkp_auth::{default_cfg, protocol, random_number_below, secret, ZKP};
+use num_bigint::BigUint;
+use wasm_bindgen::prelude::*;
+
+/// Prover runs the prover's side of the protocol in the browser, so that the secret never leaves it. Numbers are
+/// passed as big-endian byte arrays, the encoding the gRPC messages use.
+#[wasm_bindgen]
+pub struct Prover {
+    zkp: ZKP,
+}
+
+/// PublicKeys are the public keys a user registers with.
+#[wasm_bindgen]
+pub struct PublicKeys {
+    keys: protocol::PublicKeys,
+}
+
+/// Commitment holds the commitment sent to the server together with the random number k it was computed from,
+/// which never leaves the prover. It is consumed by answering the challenge to it.
+#[wasm_bindgen]
+pub struct Commitment {
+    k: BigUint,
+    commitment: protocol::Commitment,
+}
+
+#[wasm_bindgen]
+impl Prover {
+    /// new creates a prover for the default group.
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Prover {
+        let (g, h, p, q) = default_cfg();
+        Prover { zkp: ZKP { g, h, p, q } }
+    }
+
+    /// keygen generates a random secret.
+    pub fn keygen(&self) -> Vec<u8> {
+        random_number_below(&mut rand::thread_rng(), &self.zkp.q).to_bytes_be()
+    }
+
+    /// secret_from_password derives the secret of the user from a password like the command line client does.
+    #[wasm_bindgen(js_name = secretFromPassword)]
+    pub fn secret_from_password(&self, user: &str, password: &str) -> Vec<u8> {
+        secret::from_password(user, password, &self.zkp.q).to_bytes_be()
+    }
+
+    /// public_keys computes the public keys of the secret, which are sent to the server to register.
+    #[wasm_bindgen(js_name = publicKeys)]
+    pub fn public_keys(&self, secret: &[u8]) -> Result<PublicKeys, JsError> {
+        let x = self.secret(secret)?;
+        Ok(PublicKeys { keys: protocol::PublicKeys::from_secret(&self.zkp, &x) })
+    }
+
+    /// commit picks the random number k and computes the commitment to send to the server for a challenge.
+    pub fn commit(&self) -> Commitment {
+        let (k, commitment) = self.zkp.commit(&mut rand::thread_rng());
+        Commitment { k, commitment }
+    }
+
+    /// respond computes the answer s to the challenge c the server sent for the commitment.
+    pub fn respond(&self, commitment: Commitment, secret: &[u8], challenge: &[u8]) -> Result<Vec<u8>, JsError> {
+        let x = self.secret(secret)?;
+        let c = BigUint::from_bytes_be(challenge);
+        if c >= self.zkp.q {
+            return Err(JsError::new("the challenge is not below the group order"));
+        }
+        Ok(self.zkp.solve(&commitment.k, &c, &x).to_bytes_be())
+    }
+
+    /// secret decodes a secret and checks that it is below the group order.
+    fn secret(&self, secret: &[u8]) -> Result<BigUint, JsError> {
+        let x = BigUint::from_bytes_be(secret);
+        if x >= self.zkp.q {
+            return Err(JsError::new("the secret is not below the group order"));
+        }
+        Ok(x)
+    }
+}
+
+#[wasm_bindgen]
+impl PublicKeys {
+    /// y1 returns the first public key.
+    #[wasm_bindgen(getter)]
+    pub fn y1(&self) -> Vec<u8> {
+        self.keys.y1.to_bytes_be()
+    }
+
+    /// y2 returns the second public key.
+    #[wasm_bindgen(getter)]
+    pub fn y2(&self) -> Vec<u8> {
+        self.keys.y2.to_bytes_be()
+    }
+}
+
+#[wasm_bindgen]
+impl Commitment {
+    /// r1 returns the first value of the commitment.
+    #[wasm_bindgen(getter)]
+    pub fn r1(&self) -> Vec<u8> {
+        self.commitment.r1.to_bytes_be()
+    }
+
+    /// r2 returns the second value of the commitment.
+    #[wasm_bindgen(getter)]
+    pub fn r2(&self) -> Vec<u8> {
+        self.commitment.r2.to_bytes_be()
+    }
+}
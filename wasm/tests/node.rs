@@ -0,0 +1,49 @@
+//! Tests of the WebAssembly prover, run with `wasm-pack test --node` in the wasm directory.
+#![cfg(target_arch = "wasm32")]
+
+use num_bigint::BigUint;
+use wasm_bindgen_test::wasm_bindgen_test;
+use zkp_auth::{default_cfg, protocol, ZKP};
+use zkp_auth_wasm::Prover;
+
+/// verify checks the answer s to the challenge c like the server does.
+fn verify(y1: &[u8], y2: &[u8], r1: &[u8], r2: &[u8], c: &BigUint, s: &[u8]) -> bool {
+    let (g, h, p, q) = default_cfg();
+    let keys = protocol::PublicKeys { y1: BigUint::from_bytes_be(y1), y2: BigUint::from_bytes_be(y2) };
+    let commitment = protocol::Commitment { r1: BigUint::from_bytes_be(r1), r2: BigUint::from_bytes_be(r2) };
+    ZKP { g, h, p, q }.verify_proof(&keys, &commitment, c, &BigUint::from_bytes_be(s))
+}
+
+#[wasm_bindgen_test]
+fn test_login() {
+    let prover = Prover::new();
+    let secret = prover.keygen();
+    let keys = prover.public_keys(&secret).unwrap();
+
+    let commitment = prover.commit();
+    let (r1, r2) = (commitment.r1(), commitment.r2());
+    let c = BigUint::from(123456789u32);
+    let s = prover.respond(commitment, &secret, &c.to_bytes_be()).unwrap();
+    assert!(verify(&keys.y1(), &keys.y2(), &r1, &r2, &c, &s));
+
+    // a different secret does not answer the challenge
+    let commitment = prover.commit();
+    let (r1, r2) = (commitment.r1(), commitment.r2());
+    let s = prover.respond(commitment, &prover.keygen(), &c.to_bytes_be()).unwrap();
+    assert!(!verify(&keys.y1(), &keys.y2(), &r1, &r2, &c, &s));
+}
+
+#[wasm_bindgen_test]
+fn test_secret_from_password() {
+    let prover = Prover::new();
+    let secret = prover.secret_from_password("alice", "correct horse");
+    assert_eq!(secret, prover.secret_from_password("alice", "correct horse"));
+    assert_ne!(secret, prover.secret_from_password("bob", "correct horse"));
+}
+
+#[wasm_bindgen_test]
+fn test_invalid_secret() {
+    let prover = Prover::new();
+    let (_, _, _, q) = default_cfg();
+    assert!(prover.public_keys(&q.to_bytes_be()).is_err());
+}